@@ -6,25 +6,24 @@ use std::result::Result as StdResult;
 
 use basic_ddd::{
     Changable, CloneRedoStreamingStrategy, Details, Error, EventKind, FullChange, FullChanges,
-    Historic, Id, Identifiable, InMemoryStorage, KindOfEvent, Master, MasterEvent, Owned, Record,
-    Result, Stream, Streamable, Undoable,
+    Historic, Id, Identifiable, InMemoryStorage, Invariants, KindOfEvent, Master, MasterEvent,
+    Owned, Record, Result, Sequence, Stream, Streamable, Undoable,
 };
 
 fn main() -> StdResult<(), Box<dyn StdError>> {
     let mut storage = InMemoryStorage::new();
+    let mut ids = Sequence::new();
+    let create = |id: Id<Order>| create_new_order(*id.raw());
 
-    storage.save(create_new_order(0)?)?;
-
-    let mut order42 = create_new_order(42)?;
-    storage.save(order42.clone())?;
-
-    storage.save(create_new_order(1)?)?;
+    storage.save_new(&mut ids, create)?;
+    let mut order = storage.save_new(&mut ids, create)?;
+    storage.save_new(&mut ids, create)?;
 
     // println!("storage:\n{:#?}", storage);
-    let copy = storage.load(&order42.id())?;
+    let copy = storage.load(&order.id())?;
 
-    order42.forget_changes();
-    pretty_assertions::assert_eq!(order42, copy);
+    order.forget_changes();
+    pretty_assertions::assert_eq!(order, copy);
 
     println!("success!");
     Ok(())
@@ -103,7 +102,7 @@ impl Order {
     /*
      * Add item by preserving inner invariant:
      * `item_count` should match `items.len()`
-     * Item count is limited by `MAX_ORDER_ITEMS`
+     * Item count limit is checked by `Invariants` on commit
      */
     fn add_new_item(&mut self, item: impl Into<Rc<OrderItem>>) -> Result<()> {
         let item = item.into();
@@ -117,22 +116,19 @@ impl Order {
         )?;
 
         trx.mutate_inner(
-            |subj| -> Result<_> {
-                subj.validate_item_limit()?;
-                Ok(subj.master.update(|p| p.item_count += 1)?)
-            },
+            |subj| subj.master.update(|p| p.item_count += 1),
             OrderEvent::Primary,
         )?;
 
-        trx.commit();
-        Ok(())
+        trx.commit()
     }
+}
 
-    fn validate_item_limit(&self) -> Result<()> {
-        if self.master.get().item_count == MAX_ORDER_ITEMS {
-            Err(Error::from_text("Too many".into()))
-        } else {
-            Ok(())
+impl Invariants for Order {
+    fn check(&self) -> Result<()> {
+        match self.master.try_get() {
+            Some(m) if m.item_count > MAX_ORDER_ITEMS => Err(Error::from_text("Too many".into())),
+            _ => Ok(()),
         }
     }
 }
@@ -159,7 +155,7 @@ impl Changable for Order {
 }
 
 impl Streamable for Order {
-    fn stream_to<S>(&mut self, stream: &mut S) -> StdResult<usize, Box<dyn StdError>>
+    fn stream_to<S>(&mut self, stream: &mut S) -> StdResult<usize, S::Error>
     where
         S: Stream<Self::EventType>,
     {
@@ -0,0 +1,233 @@
+use crate::changable::Changable;
+use crate::identifiable::{GetId, Id};
+use crate::result::Result;
+use crate::storage::InMemoryStorage;
+use crate::streamable::{Streamable, Unstreamable};
+use crate::streaming::Stream;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::pin;
+use std::result::Result as StdResult;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+/// Asynchronous counterpart of `Stream`. Returned futures are not `Send`,
+/// so they are awaited in place (e.g. `block_on` or a single-threaded
+/// runtime) rather than passed to a multi-threaded `spawn`.
+pub trait AsyncStream<TEvent> {
+    type Error;
+
+    fn stream<I>(&mut self, events: I) -> impl Future<Output = StdResult<usize, Self::Error>>
+    where
+        I: IntoIterator<Item = TEvent>;
+}
+
+impl<S, TEvent> AsyncStream<TEvent> for &mut S
+where
+    S: AsyncStream<TEvent>,
+{
+    type Error = S::Error;
+
+    async fn stream<I>(&mut self, events: I) -> StdResult<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        (**self).stream(events).await
+    }
+}
+
+impl<TEvent> AsyncStream<TEvent> for Vec<TEvent> {
+    type Error = Infallible;
+
+    async fn stream<I>(&mut self, events: I) -> StdResult<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        Stream::stream(self, events)
+    }
+}
+
+/// Exposes synchronous stream as asynchronous one
+pub struct SyncToAsync<S>(pub S);
+
+impl<S, TEvent> AsyncStream<TEvent> for SyncToAsync<S>
+where
+    S: Stream<TEvent>,
+{
+    type Error = S::Error;
+
+    async fn stream<I>(&mut self, events: I) -> StdResult<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        self.0.stream(events)
+    }
+}
+
+/// Exposes asynchronous stream as synchronous one by blocking current
+/// thread until streaming completes
+pub struct AsyncToSync<S>(pub S);
+
+impl<S, TEvent> Stream<TEvent> for AsyncToSync<S>
+where
+    S: AsyncStream<TEvent>,
+{
+    type Error = S::Error;
+
+    fn stream<I>(&mut self, events: I) -> StdResult<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        block_on(self.0.stream(events))
+    }
+}
+
+pub trait AsyncStreamable: Streamable {
+    /// Collects changes in memory and hands them to async stream at once
+    fn stream_to_async<S>(
+        &mut self,
+        stream: &mut S,
+    ) -> impl Future<Output = StdResult<usize, S::Error>>
+    where
+        S: AsyncStream<Self::EventType>,
+    {
+        let mut events = Vec::new();
+        self.stream_to(&mut events).unwrap_or_else(|e| match e {});
+        stream.stream(events)
+    }
+}
+
+impl<T: Streamable> AsyncStreamable for T {}
+
+pub trait AsyncStorage<T: GetId> {
+    fn load_async(&mut self, id: &Id<T::IdentifiableType>) -> impl Future<Output = Result<T>>;
+
+    fn save_async(&mut self, root: &mut T) -> impl Future<Output = Result<usize>>;
+}
+
+impl<T, TEvent, TDomainEvent> AsyncStorage<T> for InMemoryStorage<T, TEvent, TDomainEvent>
+where
+    T: Unstreamable<EventType = TEvent> + Streamable + GetId,
+    TEvent: Clone,
+    Id<T::IdentifiableType>: Clone,
+    T: Changable<EventType = TEvent>,
+{
+    async fn load_async(&mut self, id: &Id<T::IdentifiableType>) -> Result<T> {
+        self.load(id)
+    }
+
+    async fn save_async(&mut self, root: &mut T) -> Result<usize> {
+        self.save(root)
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Minimal local executor which polls the future on the current thread
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{TestAggregate, TestEvent};
+    use pretty_assertions::assert_eq;
+    use std::pin::Pin;
+    use TestEvent::*;
+
+    /// Async sink which suspends once before accepting events
+    #[derive(Default)]
+    struct SlowSink {
+        received: Vec<TestEvent>,
+    }
+
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    impl AsyncStream<TestEvent> for SlowSink {
+        type Error = Infallible;
+
+        async fn stream<I>(&mut self, events: I) -> StdResult<usize, Self::Error>
+        where
+            I: IntoIterator<Item = TestEvent>,
+        {
+            YieldOnce(false).await;
+            Stream::stream(&mut self.received, events)
+        }
+    }
+
+    #[test]
+    fn should_stream_changes_asynchronously() {
+        let mut root = TestAggregate::named(1, "first");
+        let mut sink = SlowSink::default();
+
+        let count = block_on(root.stream_to_async(&mut sink)).unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(sink.received, vec![Created(1), Renamed("first")]);
+    }
+
+    #[test]
+    fn should_adapt_async_stream_to_sync() {
+        let mut root = TestAggregate::named(1, "first");
+        let mut sut = AsyncToSync(SlowSink::default());
+
+        root.stream_to(&mut sut).unwrap();
+
+        assert_eq!(sut.0.received, vec![Created(1), Renamed("first")]);
+    }
+
+    #[test]
+    fn should_adapt_sync_stream_to_async() {
+        let mut sut = SyncToAsync(Vec::new());
+
+        let count = block_on(sut.stream(vec![Created(1)])).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(sut.0, vec![Created(1)]);
+    }
+
+    #[test]
+    fn should_save_and_load_asynchronously() {
+        let mut storage = InMemoryStorage::new();
+
+        let loaded = block_on(async {
+            storage
+                .save_async(&mut TestAggregate::named(1, "first"))
+                .await
+                .unwrap();
+            storage.load_async(&Id::new(1)).await
+        });
+
+        assert_eq!(loaded.unwrap(), TestAggregate::named(1, "first"));
+    }
+}
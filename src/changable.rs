@@ -18,3 +18,47 @@ pub trait Changable: Historic {
         C::from_application_of_many(events, |e| self.apply(e))
     }
 }
+
+/// Changable which needs ambient context (e.g. clock, current user or
+/// tenant) while applying events. `Contextual` forwards its `apply` here.
+pub trait ChangableInContext<TCtx>: Historic {
+    fn apply_in_context(&mut self, context: &mut TCtx, event: Self::EventType) -> Self::EventType;
+
+    fn applied_in_context<C>(&mut self, context: &mut TCtx, e: Self::EventType) -> C
+    where
+        C: AppliedChange<Self::EventType>,
+    {
+        C::from_application(e, |e| self.apply_in_context(context, e))
+    }
+
+    fn applied_many_in_context<C>(
+        &mut self,
+        context: &mut TCtx,
+        events: impl IntoIterator<Item = Self::EventType>,
+    ) -> C
+    where
+        C: AppliedChange<Self::EventType>,
+    {
+        C::from_application_of_many(events, |e| self.apply_in_context(context, e))
+    }
+}
+
+/// Plain `Changable` which ignores the context, e.g. to put it in `Contextual`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Plain<T>(pub T);
+
+impl<T: Historic> Historic for Plain<T> {
+    type EventType = T::EventType;
+}
+
+impl<T: Changable> Changable for Plain<T> {
+    fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+        self.0.apply(event)
+    }
+}
+
+impl<T: Changable, TCtx> ChangableInContext<TCtx> for Plain<T> {
+    fn apply_in_context(&mut self, _context: &mut TCtx, event: Self::EventType) -> Self::EventType {
+        self.0.apply(event)
+    }
+}
@@ -4,18 +4,24 @@ mod smalllist;
 pub use record::*;
 use smalllist::SmallList;
 
+/// Redo may be moved out when the change is streamed
+/// (see `MoveRedoStreamingStrategy`) while undo is always kept.
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct FullChange<T> {
-    redo: T,
+    redo: Option<T>,
     undo: T,
 }
 
 impl<T> FullChange<T> {
     pub fn new(redo: T, undo: T) -> Self {
-        Self { undo, redo }
+        Self {
+            undo,
+            redo: Some(redo),
+        }
     }
 
-    pub fn take_redo(self) -> T {
+    /// `None` if redo was moved out
+    pub fn take_redo(self) -> Option<T> {
         self.redo
     }
 
@@ -23,8 +29,18 @@ impl<T> FullChange<T> {
         self.undo
     }
 
-    pub fn redo(&self) -> &T {
-        &self.redo
+    pub fn take_redo_out(&mut self) -> Option<T> {
+        self.redo.take()
+    }
+
+    /// Puts back redo which was moved out but not consumed
+    pub fn restore_redo(&mut self, redo: T) {
+        self.redo = Some(redo);
+    }
+
+    /// `None` if redo was moved out
+    pub fn redo(&self) -> Option<&T> {
+        self.redo.as_ref()
     }
 
     pub fn undo(&self) -> &T {
@@ -36,7 +52,7 @@ impl<T> FullChange<T> {
         F: Fn(T) -> O,
     {
         FullChange {
-            redo: f(self.redo),
+            redo: self.redo.map(&f),
             undo: f(self.undo),
         }
     }
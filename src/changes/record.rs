@@ -3,10 +3,75 @@ use std::iter;
 use std::ops;
 use std::slice;
 
+/// Changes which were streamed but not yet acknowledged as persisted.
+/// Obtained by `pending_changes` before streaming and passed back to
+/// `acknowledge` once the stream succeeded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PendingChanges {
+    start: usize,
+    end: usize,
+    generation: usize,
+}
+
+impl PendingChanges {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Removals of changes since tokens were taken, so that a token does not
+/// acknowledge changes made in place of removed ones
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Removals {
+    /// Count of removals so far
+    generation: usize,
+    /// Generation of the last acknowledged token, older tokens are covered by it
+    acknowledged: usize,
+    /// `(generation, history length after removal)` with lengths increasing,
+    /// as a removal to a shorter length covers earlier ones for every token
+    shrinks: Vec<(usize, usize)>,
+}
+
+impl Removals {
+    fn push(&mut self, len: usize) {
+        while let Some(&(_, shrunk)) = self.shrinks.last() {
+            if shrunk < len {
+                break;
+            }
+            self.shrinks.pop();
+        }
+        self.shrinks.push((self.generation, len));
+        self.generation += 1;
+    }
+
+    /// End of the token's changes which were not removed since it was taken.
+    /// `None` for tokens older than the last acknowledged one.
+    fn acknowledge(&mut self, token: &PendingChanges) -> Option<usize> {
+        if token.generation < self.acknowledged {
+            return None;
+        }
+        let kept = self
+            .shrinks
+            .iter()
+            .find(|(generation, _)| *generation >= token.generation)
+            .map_or(token.end, |&(_, len)| token.end.min(len));
+        self.acknowledged = token.generation;
+        self.shrinks
+            .retain(|(generation, _)| *generation >= token.generation);
+        Some(kept)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Record<T> {
     undos: Vec<T>,
     redos: Vec<T>,
+    saved: usize,
+    removals: Removals,
 }
 
 impl<T> Record<T> {
@@ -14,6 +79,8 @@ impl<T> Record<T> {
         Record {
             undos: Vec::new(),
             redos: Vec::new(),
+            saved: 0,
+            removals: Removals::default(),
         }
     }
 
@@ -21,6 +88,52 @@ impl<T> Record<T> {
         self.undos.len()
     }
 
+    /// Changes made since the last acknowledged save
+    pub fn pending(&self) -> &[T] {
+        &self.undos[self.saved..]
+    }
+
+    pub fn pending_mut(&mut self) -> &mut [T] {
+        &mut self.undos[self.saved..]
+    }
+
+    pub fn pending_changes(&self) -> PendingChanges {
+        PendingChanges {
+            start: self.saved,
+            end: self.undos.len(),
+            generation: self.removals.generation,
+        }
+    }
+
+    /// Marks changes of the token as persisted so they are neither
+    /// streamed nor undone again. Changes undone since the token was
+    /// taken stay unacknowledged, as well as changes made after that
+    /// in their place. Token older than an acknowledged one changes
+    /// nothing, as its changes are acknowledged already.
+    pub fn acknowledge(&mut self, token: PendingChanges) {
+        if let Some(kept) = self.removals.acknowledge(&token) {
+            self.saved = self.saved.max(kept.min(self.undos.len()));
+        }
+    }
+
+    fn shrink_to(&mut self, len: usize) -> Vec<T> {
+        if len >= self.undos.len() {
+            return Vec::new();
+        }
+        self.removals.push(len);
+        self.undos.drain(len..).collect()
+    }
+
+    /// Log of removals to put back by `restore_removals` once removed
+    /// changes are restored, e.g. undone and redone again for streaming
+    pub(crate) fn removals(&self) -> Removals {
+        self.removals.clone()
+    }
+
+    pub(crate) fn restore_removals(&mut self, removals: Removals) {
+        self.removals = removals;
+    }
+
     pub fn undos(&mut self) -> &[T] {
         &self.undos
     }
@@ -30,7 +143,7 @@ impl<T> Record<T> {
     }
 
     pub fn take_after(&mut self, pos: usize) -> Vec<T> {
-        self.undos.drain(pos..).collect()
+        self.shrink_to(pos.max(self.saved))
     }
 
     pub fn push_undo(&mut self, entry: T) {
@@ -49,8 +162,13 @@ impl<T> Record<T> {
         self.redos.extend(entries)
     }
 
+    /// Persisted changes cannot be undone
     pub fn pop_undo(&mut self) -> Option<T> {
-        self.undos.pop()
+        if self.undos.len() > self.saved {
+            self.shrink_to(self.undos.len() - 1).pop()
+        } else {
+            None
+        }
     }
 
     pub fn pop_redo(&mut self) -> Option<T> {
@@ -92,6 +210,8 @@ impl<T> iter::FromIterator<T> for Record<T> {
         Self {
             undos: iter.into_iter().collect(),
             redos: Vec::new(),
+            saved: 0,
+            removals: Removals::default(),
         }
     }
 }
@@ -128,4 +248,75 @@ mod tests {
 
         assert_eq!(sut.history_len(), 1);
     }
+
+    #[test]
+    fn should_keep_acknowledged_changes_from_undo() {
+        let mut sut: Record<_> = vec![1, 2].into_iter().collect();
+        let token = sut.pending_changes();
+        sut.push_undo(3);
+
+        sut.acknowledge(token);
+
+        assert_eq!(sut.pending(), &[3]);
+        assert_eq!(sut.pop_undo(), Some(3));
+        assert_eq!(sut.pop_undo(), None);
+        assert_eq!(sut.history_len(), 2);
+    }
+
+    #[test]
+    fn should_not_acknowledge_undone_changes() {
+        let mut sut: Record<_> = vec![1, 2].into_iter().collect();
+        let token = sut.pending_changes();
+        sut.pop_undo();
+
+        sut.acknowledge(token);
+
+        assert_eq!(sut.pending(), &[] as &[i32]);
+        assert_eq!(sut.pending_changes().len(), 0);
+        assert_eq!(sut.history_len(), 1);
+    }
+
+    #[test]
+    fn should_not_acknowledge_changes_made_in_place_of_undone() {
+        let mut sut: Record<_> = vec![1, 2].into_iter().collect();
+        let token = sut.pending_changes();
+        sut.pop_undo();
+        sut.push_undo(3);
+
+        sut.acknowledge(token);
+
+        assert_eq!(sut.pending(), &[3]);
+        assert_eq!(sut.history_len(), 2);
+    }
+
+    #[test]
+    fn should_keep_removal_log_bounded_by_history() {
+        let mut sut: Record<_> = vec![1, 2].into_iter().collect();
+        let token = sut.pending_changes();
+
+        for x in 0..100 {
+            sut.push_undo(x);
+            sut.pop_undo();
+        }
+
+        assert_eq!(sut.removals.shrinks.len(), 1);
+        sut.acknowledge(token);
+        assert_eq!(sut.pending(), &[] as &[i32]);
+        assert_eq!(sut.removals.shrinks.len(), 1);
+    }
+
+    #[test]
+    fn should_ignore_token_older_than_acknowledged_one() {
+        let mut sut: Record<_> = vec![1, 2].into_iter().collect();
+        let old = sut.pending_changes();
+        sut.pop_undo();
+        sut.push_undo(3);
+        let new = sut.pending_changes();
+
+        sut.acknowledge(new);
+        sut.acknowledge(old);
+
+        assert_eq!(sut.pending(), &[] as &[i32]);
+        assert!(sut.removals.shrinks.is_empty());
+    }
 }
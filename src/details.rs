@@ -1,19 +1,36 @@
 use super::identifiable::*;
-use crate::changable::Changable;
+use crate::changable::{Changable, ChangableInContext};
 use crate::change_abs::{AppliedChange, NoopChange};
 use crate::changes::FullChanges;
 use crate::historic::Historic;
-use crate::result::{AlreadyExists, NotFound};
+use crate::invariants::{ValidationContext, Validator};
+use crate::query::{IndexOps, Indexed, Query, Queryable, SecondaryIndex};
+use crate::result::{
+    AddError, AlreadyExists, Error, NotFound, Result, UniqueViolation, UpdateError,
+};
 use std::cmp::{Eq, PartialEq};
 use std::fmt;
 use std::hash;
 use std::marker;
 use std::mem;
 use std::ops;
+use std::rc::Rc;
 use std::result::Result as StdResult;
 use std::slice;
 use DetailsEvent::*;
 
+/// Tells whether two items share the same unique key
+type UniqueKey<T> = Rc<dyn Fn(&T, &T) -> bool>;
+
+/// Aggregate which can find its details by qualified id
+pub trait DetailsOwner<D>
+where
+    D: GetId,
+    D::IdentifiableType: Owned,
+{
+    fn detail_by_qualified_id(&self, id: &QualifiedId<D::IdentifiableType>) -> Option<&D>;
+}
+
 pub enum DetailsEvent<T>
 where
     T: GetId,
@@ -126,6 +143,10 @@ where
 {
     inner: Vec<T>,
     complete: bool,
+    validator: Option<Validator<T>>,
+    violations: Vec<(Id<T::IdentifiableType>, Error)>,
+    indexes: Vec<Box<dyn IndexOps<T>>>,
+    unique_keys: Vec<(&'static str, UniqueKey<T>)>,
     marker: marker::PhantomData<C>,
 }
 
@@ -185,6 +206,18 @@ where
     }
 }
 
+impl<T, C> Queryable for Details<T, C>
+where
+    T: GetId,
+    T::IdentifiableType: Owned,
+{
+    type Item = T;
+
+    fn query(&self) -> Query<'_, T> {
+        Query::new(&self.inner)
+    }
+}
+
 impl<T, C> Default for Details<T, C>
 where
     T: GetId + Clone,
@@ -203,11 +236,16 @@ where
     T: GetId + Clone,
     T::IdentifiableType: Owned,
     DetailsEvent<T>: Clone,
+    Id<T::IdentifiableType>: Clone,
 {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
             complete: self.complete,
+            validator: self.validator.clone(),
+            violations: self.violations.clone(),
+            indexes: self.indexes.iter().map(|x| x.clone_box()).collect(),
+            unique_keys: self.unique_keys.clone(),
             marker: self.marker,
         }
     }
@@ -232,17 +270,29 @@ where
         match event {
             Created(x) => {
                 let id = x.get_id();
+                self.validate(&id, Some(&x));
+                for index in &mut self.indexes {
+                    index.inserted(self.inner.len(), &x);
+                }
                 self.inner.push(x);
                 Deleted(id)
             }
             Updated(x) => {
                 let id = x.get_id();
+                self.validate(&id, Some(&x));
                 let pos = self.position_by_id(&id).expect("Dev error: id not found");
+                for index in &mut self.indexes {
+                    index.replaced(pos, &self.inner[pos], &x);
+                }
                 let old = mem::replace(&mut self.inner[pos], x);
                 Updated(old)
             }
             Deleted(id) => {
+                self.validate(&id, None);
                 let pos = self.position_by_id(&id).expect("Dev error: id not found");
+                for index in &mut self.indexes {
+                    index.removed(pos, &self.inner[pos]);
+                }
                 let old = self.inner.remove(pos);
                 Created(old)
             }
@@ -250,6 +300,32 @@ where
     }
 }
 
+impl<T, C, TCtx> ChangableInContext<TCtx> for Details<T, C>
+where
+    T: GetId,
+    T::IdentifiableType: Owned,
+    Id<T::IdentifiableType>: hash::Hash + Clone,
+    Id<<T::IdentifiableType as Owned>::OwnerType>: Clone,
+    TCtx: ValidationContext<T>,
+{
+    fn apply_in_context(&mut self, context: &mut TCtx, event: Self::EventType) -> Self::EventType {
+        let id = match &event {
+            Created(x) | Updated(x) => Some(x.get_id()),
+            Deleted(_) => None,
+        };
+        let undo = self.apply(event);
+        if let Some(id) = id {
+            if self.violations.iter().all(|(x, _)| x != &id) {
+                let pos = self.position_by_id(&id).expect("Dev error: id not found");
+                if let Err(e) = context.validate(&self.inner[pos]) {
+                    self.violations.push((id, e));
+                }
+            }
+        }
+        undo
+    }
+}
+
 impl<T, C> Details<T, C>
 where
     T: GetId,
@@ -259,14 +335,175 @@ where
         Self {
             inner: Vec::new(),
             complete: true,
+            validator: None,
+            violations: Vec::new(),
+            indexes: Vec::new(),
+            unique_keys: Vec::new(),
             marker: marker::PhantomData,
         }
     }
 
+    /// Validates items on each creation and update, as well as existing items
+    /// right away. Violations are reported by `check` until items become valid
+    /// again or are removed.
+    pub fn with_validator<F>(mut self, validator: F) -> Self
+    where
+        F: 'static + Fn(&T) -> Result<()>,
+    {
+        self.violations = self
+            .inner
+            .iter()
+            .filter_map(|x| validator(x).err().map(|e| (x.get_id(), e)))
+            .collect();
+        self.validator = Some(Rc::new(validator));
+        self
+    }
+
+    /// Rejects items which share the `key` with another item of different id
+    pub fn with_unique_key<K, F>(mut self, name: &'static str, key: F) -> Self
+    where
+        K: Eq,
+        F: 'static + Fn(&T) -> K,
+    {
+        self.unique_keys
+            .push((name, Rc::new(move |x, y| key(x) == key(y))));
+        self
+    }
+
+    /// Maintains secondary index `I` so that `by_index::<I>` does not scan.
+    /// The index keeps item positions, so deletion updates all of them.
+    pub fn with_index<I>(mut self) -> Self
+    where
+        I: Indexed<T>,
+        T: 'static,
+    {
+        let index = SecondaryIndex::<I, T>::build(&self.inner);
+        self.indexes.push(Box::new(index));
+        self
+    }
+
+    /// Items with the given key in order of the collection. Falls back
+    /// to a scan when `I` was not registered via `with_index`.
+    pub fn by_index<I>(&self, key: &I::Key) -> Query<'_, T>
+    where
+        I: Indexed<T>,
+        T: 'static,
+    {
+        let index = self
+            .indexes
+            .iter()
+            .find_map(|x| x.as_any().downcast_ref::<SecondaryIndex<I, T>>());
+        match index {
+            Some(index) => Query::new(index.positions(key).iter().map(|&pos| &self.inner[pos])),
+            None => Query::new(self.inner.iter().filter(|x| &I::key(x) == key)),
+        }
+    }
+
+    pub fn check(&self) -> Result<()> {
+        match self.violations.first() {
+            Some((_, e)) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn validate(&mut self, id: &Id<T::IdentifiableType>, item: Option<&T>) {
+        self.violations.retain(|(x, _)| x != id);
+        if let (Some(validator), Some(item)) = (&self.validator, item) {
+            if let Err(e) = validator(item) {
+                self.violations.push((item.get_id(), e));
+            }
+        }
+    }
+
     fn position_by_id(&self, id: &Id<T::IdentifiableType>) -> Option<usize> {
         self.inner.iter().position(|x| &x.get_id() == id)
     }
 
+    /// Changes the item in place, so it is not copied, and refreshes indexes
+    /// and validation of it afterwards. `change` must not change the item id.
+    pub(crate) fn change_in_place<R, F>(
+        &mut self,
+        id: &Id<T::IdentifiableType>,
+        change: F,
+    ) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let pos = self.position_by_id(id)?;
+        for index in &mut self.indexes {
+            index.detached(pos, &self.inner[pos]);
+        }
+        let result = change(&mut self.inner[pos]);
+        let item = &self.inner[pos];
+        assert!(&item.get_id() == id, "Dev error: item id must not change");
+        for index in &mut self.indexes {
+            index.attached(pos, item);
+        }
+        self.violations.retain(|(x, _)| x != id);
+        if let Some(validator) = &self.validator {
+            if let Err(e) = validator(item) {
+                self.violations.push((item.get_id(), e));
+            }
+        }
+        Some(result)
+    }
+
+    /// Name of the first unique key `item` shares with another item of `others`
+    fn clashing_key<'a>(
+        &self,
+        item: &T,
+        others: impl IntoIterator<Item = &'a T>,
+    ) -> Option<&'static str>
+    where
+        T: 'a,
+    {
+        let id = item.get_id();
+        others
+            .into_iter()
+            .filter(|x| x.get_id() != id)
+            .find_map(|x| {
+                self.unique_keys
+                    .iter()
+                    .find(|(_, clash)| clash(item, x))
+                    .map(|(name, _)| *name)
+            })
+    }
+
+    pub(crate) fn check_unique(&self, item: T) -> StdResult<T, UniqueViolation<T>> {
+        match self.clashing_key(&item, &self.inner) {
+            Some(key) => Err(UniqueViolation { key, item }),
+            None => Ok(item),
+        }
+    }
+
+    /// Checks items which are going to replace all existing ones except `kept`
+    fn check_all_unique<K>(
+        &self,
+        mut items: Vec<T>,
+        mut kept: K,
+    ) -> StdResult<Vec<T>, UniqueViolation<T>>
+    where
+        K: FnMut(&T) -> bool,
+    {
+        let new_ids: Vec<_> = items.iter().map(GetId::get_id).collect();
+        let kept: Vec<&T> = self
+            .inner
+            .iter()
+            .filter(|x| kept(x) && !new_ids.contains(&x.get_id()))
+            .collect();
+        let clash = items.iter().enumerate().find_map(|(i, x)| {
+            let others = kept.iter().copied().chain(&items[..i]);
+            self.clashing_key(x, others).map(|key| (i, key))
+        });
+        match clash {
+            Some((i, key)) => Err(UniqueViolation {
+                key,
+                item: items.swap_remove(i),
+            }),
+            None => Ok(items),
+        }
+    }
+
     pub fn get<I>(&self, index: I) -> Option<&<I as slice::SliceIndex<[T]>>::Output>
     where
         I: slice::SliceIndex<[T]>,
@@ -291,6 +528,19 @@ where
         self.find(|x| &x.get_id() == id)
     }
 
+    /// Finds item only if the qualified id belongs to `owner` of these details
+    pub fn by_qualified_id(
+        &self,
+        owner: &Id<<T::IdentifiableType as Owned>::OwnerType>,
+        id: &QualifiedId<T::IdentifiableType>,
+    ) -> Option<&T> {
+        if id.owner() == owner {
+            self.by_id(id.id())
+        } else {
+            None
+        }
+    }
+
     pub fn find<P>(&self, mut predicate: P) -> Option<&T>
     where
         P: FnMut(&T) -> bool,
@@ -305,11 +555,16 @@ where
     /// Replaces `criteria` matching items in a collection and returns diff-change
     /// which represents removal, update and creation of items as
     /// necessary
-    pub fn set_some<P>(&mut self, mut criteria: P, items: impl IntoIterator<Item = T>) -> C
+    pub fn set_some<P>(
+        &mut self,
+        mut criteria: P,
+        items: impl IntoIterator<Item = T>,
+    ) -> StdResult<C, UniqueViolation<T>>
     where
         T: Eq + fmt::Debug,
         P: FnMut(&T) -> bool,
     {
+        let items = self.check_all_unique(items.into_iter().collect(), |x| !criteria(x))?;
         let mut changes = Vec::new();
 
         let mut existing_ids: Vec<_> = self
@@ -341,16 +596,20 @@ where
             changes.push(Deleted(id));
         }
 
-        self.applied_many(changes)
+        Ok(self.applied_many(changes))
     }
 
     /// Replaces all items in a collection and returns diff-change
     /// which represents removal, update and creation of items as
     /// necessary
-    pub fn set_all(&mut self, items: impl IntoIterator<Item = T>) -> C
+    pub fn set_all(
+        &mut self,
+        items: impl IntoIterator<Item = T>,
+    ) -> StdResult<C, UniqueViolation<T>>
     where
         T: Eq + fmt::Debug,
     {
+        let items = self.check_all_unique(items.into_iter().collect(), |_| false)?;
         let mut changes = Vec::new();
 
         let mut existing_ids: Vec<_> = self.inner.iter().map(GetId::get_id).collect();
@@ -377,23 +636,58 @@ where
             changes.push(Deleted(id));
         }
 
-        self.applied_many(changes)
+        Ok(self.applied_many(changes))
     }
 
-    pub fn update_or_add(&mut self, item: T) -> C
+    pub fn update_or_add(&mut self, item: T) -> StdResult<C, UniqueViolation<T>>
     where
         T: Eq + fmt::Debug,
         C: NoopChange,
     {
-        self.update(item)
-            .or_else(|NotFound(x)| self.add_new(x))
-            .unwrap()
+        Ok(match self.update_or_add_event(item)? {
+            Some(e) => self.applied(e),
+            None => C::noop(),
+        })
+    }
+
+    /// Like `update_or_add` but the item is first prepared with ambient
+    /// context, e.g. stamped by a clock, which also validates it
+    pub fn update_or_add_in_context<TCtx, F>(
+        &mut self,
+        context: &mut TCtx,
+        mut item: T,
+        prepare: F,
+    ) -> StdResult<C, UniqueViolation<T>>
+    where
+        F: FnOnce(&mut TCtx, &mut T),
+        T: Eq + fmt::Debug,
+        C: NoopChange,
+        TCtx: ValidationContext<T>,
+    {
+        prepare(context, &mut item);
+        Ok(match self.update_or_add_event(item)? {
+            Some(e) => self.applied_in_context(context, e),
+            None => C::noop(),
+        })
+    }
+
+    /// Event which updates or adds the item, `None` if it is unchanged
+    fn update_or_add_event(&self, item: T) -> StdResult<Option<DetailsEvent<T>>, UniqueViolation<T>>
+    where
+        T: Eq + fmt::Debug,
+    {
+        let item = self.check_unique(item)?;
+        Ok(match self.position_by_id(&item.get_id()) {
+            Some(pos) if item == self.inner[pos] => None,
+            Some(_) => Some(Updated(item)),
+            None => Some(Created(item)),
+        })
     }
 
     /**
      * Updates existing item or returns item back as a Result::Err
      */
-    pub fn update(&mut self, item: T) -> StdResult<C, NotFound<T>>
+    pub fn update(&mut self, item: T) -> StdResult<C, UpdateError<T>>
     where
         C: NoopChange,
         T: Eq,
@@ -402,27 +696,30 @@ where
             if &item == ops::Index::index(self, pos) {
                 Ok(C::noop())
             } else {
+                let item = self.check_unique(item)?;
                 Ok(self.applied(Updated(item)))
             }
         } else {
-            Err(NotFound(item))
+            Err(NotFound(item).into())
         }
     }
 
     /**
      * Inserts a new item and returns `Ok(())` if item with the same id does not exist.
-     * Returns `Err(item)` if item with the same already exists.
+     * Returns `Err(item)` if item with the same already exists or shares
+     * a unique key with another item.
      */
-    pub fn add_new(&mut self, item: T) -> StdResult<C, AlreadyExists<T>>
+    pub fn add_new(&mut self, item: T) -> StdResult<C, AddError<T>>
     where
         C: NoopChange,
         T: Eq,
     {
         let id = item.get_id();
         if let None = self.position_by_id(&id) {
+            let item = self.check_unique(item)?;
             Ok(self.applied(Created(item)))
         } else {
-            Err(AlreadyExists(item))
+            Err(AlreadyExists(item).into())
         }
     }
 
@@ -468,7 +765,7 @@ mod tests {
         }
     }
 
-    #[derive(Debug, Eq, PartialEq)]
+    #[derive(Debug, Clone, Eq, PartialEq)]
     struct TestEntry {
         owner_id: i32,
         child_id: String,
@@ -535,9 +832,10 @@ mod tests {
 
     fn setup_existing() -> Sut {
         let mut sut = Details::new();
-        sut.update_or_add(colored(ANY_NOT_USED_ENTRY_ID, None));
-        sut.update_or_add(colored(EXISTING_ID, None));
-        sut.update_or_add(colored(DELETED_ID, None));
+        sut.update_or_add(colored(ANY_NOT_USED_ENTRY_ID, None))
+            .unwrap();
+        sut.update_or_add(colored(EXISTING_ID, None)).unwrap();
+        sut.update_or_add(colored(DELETED_ID, None)).unwrap();
         sut
     }
 
@@ -547,7 +845,7 @@ mod tests {
 
         let mut changes = FullChanges::<DetailsEvent<Rc<TestEntry>>>::new();
 
-        changes.append(sut.update_or_add(colored(NEW_ID, Red)));
+        changes.append(sut.update_or_add(colored(NEW_ID, Red)).unwrap());
 
         assert_eq!(
             sorted(changes.into()),
@@ -562,7 +860,7 @@ mod tests {
     fn update_event_is_emitted() {
         let mut sut = setup_existing();
 
-        let changes: Vec<_> = sut.update_or_add(colored(EXISTING_ID, Red)).into();
+        let changes: Vec<_> = sut.update_or_add(colored(EXISTING_ID, Red)).unwrap().into();
 
         assert_eq!(
             changes,
@@ -605,6 +903,7 @@ mod tests {
                 colored(NEW_ID, Red),                 // to create
                 // colored(DELETED_ID, None),         // to delete
             ])
+            .unwrap()
             .into();
 
         assert_eq!(
@@ -638,6 +937,7 @@ mod tests {
                     colored(NEW_ID, Red),                 // to create
                     // colored(DELETED_ID, None),         // to delete
                 ])
+            .unwrap()
             .into();
 
         assert_eq!(
@@ -670,11 +970,198 @@ mod tests {
                     colored(EXISTING_ID, None),
                     colored(DELETED_ID, None),
                 ])
+            .unwrap()
             .into();
 
         assert_eq!(changes, vec![]);
     }
 
+    #[test]
+    fn should_report_invalid_items_until_removed() {
+        let mut sut = Sut::new().with_validator(|x| {
+            if x.name == "Red" {
+                Err(Error::from_text("red is not allowed".into()))
+            } else {
+                Ok(())
+            }
+        });
+        sut.add_new(colored(EXISTING_ID, None)).unwrap();
+        assert_eq!(sut.check(), Ok(()));
+
+        let changes = sut.update_or_add(colored(NEW_ID, Red)).unwrap();
+        assert_eq!(
+            sut.check(),
+            Err(Error::from_text("red is not allowed".into()))
+        );
+
+        for c in changes {
+            sut.apply(c.take_undo());
+        }
+        assert_eq!(sut.check(), Ok(()));
+    }
+
+    impl ValidationContext<Rc<TestEntry>> for Vec<&str> {
+        fn validate(&mut self, entity: &Rc<TestEntry>) -> Result<()> {
+            if self.contains(&entity.name.as_str()) {
+                Err(Error::validation("name is still to come"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn should_validate_item_in_context() {
+        let mut sut = Sut::new();
+        let mut next_name = vec!["Red", "Red"];
+        let rename = |names: &mut Vec<&str>, x: &mut Rc<TestEntry>| {
+            Rc::make_mut(x).name = names.pop().unwrap().to_string();
+        };
+
+        sut.update_or_add_in_context(&mut next_name, colored(NEW_ID, None), rename)
+            .unwrap();
+
+        assert_eq!(sut.check(), Err(Error::validation("name is still to come")));
+    }
+
+    #[test]
+    fn should_prepare_item_with_context() {
+        let mut sut = unique_by_name();
+        let mut next_name = vec!["Green", "Red"];
+        let rename = |names: &mut Vec<&str>, x: &mut Rc<TestEntry>| {
+            Rc::make_mut(x).name = names.pop().unwrap().to_string();
+        };
+
+        sut.update_or_add_in_context(&mut next_name, colored(NEW_ID, None), rename)
+            .unwrap();
+        let clash =
+            sut.update_or_add_in_context(&mut vec!["Red"], colored(EXISTING_ID, None), rename);
+        sut.update_or_add_in_context(&mut next_name, colored(NEW_ID, None), rename)
+            .unwrap();
+
+        assert!(clash.is_err());
+        assert_eq!(
+            sut.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+            vec!["Green"]
+        );
+    }
+
+    fn unique_by_name() -> Sut {
+        Sut::new().with_unique_key("name", |x| x.name.clone())
+    }
+
+    #[test]
+    fn should_reject_items_sharing_unique_key() {
+        let mut sut = unique_by_name();
+        sut.add_new(colored(EXISTING_ID, Red)).unwrap();
+
+        let added = sut.add_new(colored(NEW_ID, Red));
+        let updated = sut.update_or_add(colored(NEW_ID, Red));
+
+        assert!(matches!(
+            added,
+            Err(AddError::UniqueViolation(UniqueViolation {
+                key: "name",
+                ..
+            }))
+        ));
+        assert!(matches!(updated, Err(UniqueViolation { key: "name", .. })));
+        assert_eq!(sut.len(), 1);
+    }
+
+    #[test]
+    fn should_allow_item_to_keep_its_own_key() {
+        let mut sut = unique_by_name();
+        sut.add_new(colored(EXISTING_ID, Red)).unwrap();
+        sut.add_new(colored(NEW_ID, Green)).unwrap();
+
+        let mut renamed = (*colored(EXISTING_ID, Red)).clone();
+        renamed.owner_id = 2;
+        sut.update(renamed.into()).unwrap();
+
+        assert!(matches!(
+            sut.update(colored(EXISTING_ID, Green)),
+            Err(UpdateError::UniqueViolation(_))
+        ));
+    }
+
+    #[test]
+    fn should_check_unique_keys_of_replacing_items() {
+        let mut sut = unique_by_name();
+        sut.add_new(colored(EXISTING_ID, Red)).unwrap();
+        sut.add_new(colored(IGNORED_ID, Blue)).unwrap();
+
+        let swapped = sut.set_all(vec![colored(EXISTING_ID, Blue), colored(NEW_ID, Red)]);
+        assert!(swapped.is_ok());
+
+        let duplicated = sut.set_all(vec![colored(EXISTING_ID, Red), colored(NEW_ID, Red)]);
+        let clashing_with_kept = sut.set_some(
+            |x| x.child_id == raw_colored_id(NEW_ID),
+            vec![colored(NEW_ID, Blue)],
+        );
+
+        assert!(matches!(duplicated, Err(UniqueViolation { .. })));
+        assert!(matches!(clashing_with_kept, Err(UniqueViolation { .. })));
+        assert_eq!(sut.len(), 2);
+    }
+
+    #[test]
+    fn should_free_unique_key_on_undo() {
+        let mut sut = unique_by_name();
+        let changes = sut.add_new(colored(EXISTING_ID, Red)).unwrap();
+
+        for c in changes {
+            sut.apply(c.take_undo());
+        }
+
+        assert!(sut.add_new(colored(NEW_ID, Red)).is_ok());
+    }
+
+    struct ByName;
+
+    impl Indexed<Rc<TestEntry>> for ByName {
+        type Key = String;
+
+        fn key(item: &Rc<TestEntry>) -> String {
+            item.name.clone()
+        }
+    }
+
+    fn ids_by_name(sut: &Sut, name: &str) -> Vec<String> {
+        sut.by_index::<ByName>(&name.to_string())
+            .into_iter()
+            .map(|x| x.child_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn should_maintain_index_on_changes_and_undo() {
+        let mut sut = setup_existing().with_index::<ByName>();
+        assert_eq!(ids_by_name(&sut, "None"), vec!["10000", "0", "1"]);
+
+        sut.update_or_add(colored(EXISTING_ID, Red)).unwrap();
+        sut.remove_by_id(&colored_id(ANY_NOT_USED_ENTRY_ID))
+            .unwrap();
+        let changes = sut.update_or_add(colored(NEW_ID, Red)).unwrap();
+        assert_eq!(ids_by_name(&sut, "None"), vec!["1"]);
+        assert_eq!(ids_by_name(&sut, "Red"), vec!["0", "2"]);
+
+        for c in changes {
+            sut.apply(c.take_undo());
+        }
+        assert_eq!(ids_by_name(&sut, "Red"), vec!["0"]);
+        assert_eq!(ids_by_name(&sut, "Green"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn should_scan_when_index_is_not_registered() {
+        let mut sut = setup_existing();
+        sut.update_or_add(colored(DELETED_ID, Blue)).unwrap();
+
+        assert_eq!(ids_by_name(&sut, "Blue"), vec!["1"]);
+        assert_eq!(sut.query().where_(|x| x.name == "None").count(), 2);
+    }
+
     fn sorted<T>(mut changes: Vec<FullChange<DetailsEvent<T>>>) -> Vec<FullChange<DetailsEvent<T>>>
     where
         T: GetId,
@@ -682,7 +1169,7 @@ mod tests {
         Id<T::IdentifiableType>: hash::Hash + Clone + Ord,
         Id<<T::IdentifiableType as Owned>::OwnerType>: Clone,
     {
-        changes.sort_by_key(|c| DetailsEvent::get_id(c.redo()));
+        changes.sort_by_key(|c| c.redo().and_then(DetailsEvent::get_id));
         changes
     }
 }
@@ -0,0 +1,151 @@
+use crate::undoable::Undoable;
+use std::any::Any;
+use std::mem;
+
+/// Aggregate which raises business facts (e.g. `OrderPlaced`) in addition
+/// to the change events used for undo/redo and persistence.
+///
+/// Events should be raised via `Atomic::raise` so that rollback discards them.
+/// They are kept in the `Vec` returned by `Undoable::raised_events`, so that
+/// rollback of an outer transaction also discards events committed by nested
+/// ones.
+pub trait DomainEventSource: Undoable {
+    type DomainEvent: 'static;
+
+    fn domain_events_mut(&mut self) -> &mut Vec<Self::DomainEvent> {
+        self.raised_events()
+            .and_then(|x| x.as_any_mut().downcast_mut())
+            .expect("Dev error: raised_events should return Vec of domain events")
+    }
+
+    fn take_domain_events(&mut self) -> Vec<Self::DomainEvent> {
+        mem::take(self.domain_events_mut())
+    }
+}
+
+/// Raised domain events which rollback cuts back to an earlier count
+pub trait RaisedEvents {
+    fn count(&self) -> usize;
+
+    fn discard_after(&mut self, count: usize);
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<E: 'static> RaisedEvents for Vec<E> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn discard_after(&mut self, count: usize) {
+        self.truncate(count)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changable::Changable;
+    use crate::changes::{FullChange, FullChanges, Record};
+    use crate::historic::Historic;
+    use crate::invariants::Invariants;
+    use crate::result::{Error, Result};
+    use crate::undoable::Undoable;
+    use pretty_assertions::assert_eq;
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    enum Placed {
+        Placed(u32),
+    }
+
+    #[derive(Default)]
+    struct TestOrder {
+        total: u32,
+        changes: Record<FullChange<u32>>,
+        domain_events: Vec<Placed>,
+    }
+
+    impl TestOrder {
+        fn place(&mut self, total: u32) -> Result<()> {
+            let mut trx = self.begin_changes();
+            trx.mutate(|subj| Ok::<_, Error>(subj.applied::<FullChanges<_>>(total)))?;
+            trx.raise(Placed::Placed(total));
+            trx.commit()
+        }
+
+        fn place_twice(&mut self, first: u32, second: u32) -> Result<()> {
+            let mut trx = self.begin_changes();
+            trx.invoke(|subj| subj.place(first))?;
+            trx.invoke(|subj| subj.place(second))?;
+            trx.commit()
+        }
+    }
+
+    impl Historic for TestOrder {
+        type EventType = u32;
+    }
+
+    impl Changable for TestOrder {
+        fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+            mem::replace(&mut self.total, event)
+        }
+    }
+
+    impl Invariants for TestOrder {
+        fn check(&self) -> Result<()> {
+            if self.total > 100 {
+                Err(Error::from_text("Too expensive".into()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Undoable for TestOrder {
+        fn changes_mut(&mut self) -> &mut Record<FullChange<Self::EventType>> {
+            &mut self.changes
+        }
+
+        fn raised_events(&mut self) -> Option<&mut dyn RaisedEvents> {
+            Some(&mut self.domain_events)
+        }
+    }
+
+    impl DomainEventSource for TestOrder {
+        type DomainEvent = Placed;
+    }
+
+    #[test]
+    fn should_record_domain_events_on_commit() {
+        let mut sut = TestOrder::default();
+
+        sut.place(10).unwrap();
+
+        assert_eq!(sut.take_domain_events(), vec![Placed::Placed(10)]);
+        assert_eq!(sut.take_domain_events(), vec![]);
+    }
+
+    #[test]
+    fn should_discard_domain_events_on_rollback() {
+        let mut sut = TestOrder::default();
+
+        assert!(sut.place(1000).is_err());
+
+        assert_eq!(sut.total, 0);
+        assert_eq!(sut.take_domain_events(), vec![]);
+    }
+
+    #[test]
+    fn should_discard_events_of_nested_commit_on_outer_rollback() {
+        let mut sut = TestOrder::default();
+
+        assert!(sut.place_twice(10, 1000).is_err());
+
+        assert_eq!(sut.total, 0);
+        assert_eq!(sut.take_domain_events(), vec![]);
+    }
+}
@@ -0,0 +1,517 @@
+use crate::identifiable::{GetId, Id, Identifiable};
+use crate::result::{Error, Result};
+use crate::streamable::{KindOfEvent, Streamable, Unstreamable};
+use crate::upcasting::UpcasterChain;
+use std::any::{self, TypeId};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+
+/// Event which can be written to and read from a text payload
+pub trait EventCodec: Sized {
+    /// Schema version of payloads produced by `encode`
+    const VERSION: u32 = 1;
+
+    fn encode(&self) -> String;
+
+    fn decode(payload: &str) -> Result<Self>;
+}
+
+/// Aggregate kept in `EventStore` under its own stream type name.
+/// Names must be unique among aggregates sharing the store.
+pub trait StoredAggregate: Streamable + Unstreamable + GetId
+where
+    Self::EventType: EventCodec,
+{
+    const STREAM_TYPE: &'static str;
+}
+
+/// Event of any aggregate type in the order of the global feed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredEvent {
+    /// Position in the global feed starting from 1
+    pub sequence: u64,
+    pub stream_type: &'static str,
+    /// Aggregate id in its text form, see `Id::from_str`
+    pub stream_id: String,
+    /// Schema version the payload was written with
+    pub version: u32,
+    pub payload: String,
+}
+
+/// Store which holds event streams of several aggregate types with
+/// a single global order. Accessed per type via `of`. Unlike
+/// `InMemoryStorage` it keeps events as versioned payloads, so it is
+/// the storage for persisted events.
+#[derive(Default)]
+pub struct EventStore {
+    events: Vec<StoredEvent>,
+    upcasters: UpcasterChain,
+    stream_types: HashMap<&'static str, (TypeId, &'static str)>,
+}
+
+impl fmt::Debug for EventStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventStore")
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+impl EventStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store which upgrades payloads of older schema versions on load
+    pub fn with_upcasters(upcasters: UpcasterChain) -> Self {
+        Self {
+            upcasters,
+            ..Self::default()
+        }
+    }
+
+    /// Handle with `load`/`save` API for aggregates of type `T`. Fails if
+    /// another type already uses the same `STREAM_TYPE` in this store.
+    pub fn of<T>(&mut self) -> Result<TypedStore<'_, T>>
+    where
+        T: 'static + StoredAggregate,
+        T::EventType: EventCodec,
+    {
+        let (type_id, type_name) = *self
+            .stream_types
+            .entry(T::STREAM_TYPE)
+            .or_insert((TypeId::of::<T>(), any::type_name::<T>()));
+        if type_id != TypeId::of::<T>() {
+            return Err(Error::validation(format!(
+                "Stream type {} of {} is already used by {}",
+                T::STREAM_TYPE,
+                any::type_name::<T>(),
+                type_name
+            )));
+        }
+        Ok(TypedStore {
+            store: self,
+            marker: PhantomData,
+        })
+    }
+
+    /// Events of all types written after the given sequence number
+    pub fn feed(&self, after: u64) -> impl Iterator<Item = &StoredEvent> {
+        self.events.iter().filter(move |x| x.sequence > after)
+    }
+
+    pub fn last_sequence(&self) -> u64 {
+        self.events.last().map_or(0, |x| x.sequence)
+    }
+
+    fn append(
+        &mut self,
+        stream_type: &'static str,
+        stream_id: String,
+        version: u32,
+        payload: String,
+    ) {
+        let sequence = self.last_sequence() + 1;
+        self.events.push(StoredEvent {
+            sequence,
+            stream_type,
+            stream_id,
+            version,
+            payload,
+        });
+    }
+
+    fn decode<E: EventCodec>(&self, stored: &StoredEvent) -> Result<E> {
+        decode_versioned(
+            &self.upcasters,
+            stored.stream_type,
+            stored.version,
+            &stored.payload,
+        )
+    }
+}
+
+fn decode_versioned<E: EventCodec>(
+    upcasters: &UpcasterChain,
+    stream_type: &str,
+    version: u32,
+    payload: &str,
+) -> Result<E> {
+    if version == E::VERSION {
+        E::decode(payload)
+    } else {
+        E::decode(&upcasters.upcast(stream_type, version, payload, E::VERSION)?)
+    }
+}
+
+/// Test helper which loads aggregate from archived `(version, payload)`
+/// events of its stream, e.g. to check that upcasters still fit fixtures
+pub fn replay_fixture<T>(upcasters: &UpcasterChain, fixture: &[(u32, &str)]) -> Result<T>
+where
+    T: StoredAggregate,
+    T::EventType: EventCodec,
+{
+    let events = fixture
+        .iter()
+        .map(|(version, payload)| decode_versioned(upcasters, T::STREAM_TYPE, *version, payload))
+        .collect::<Result<Vec<_>>>()?;
+    T::load(events)
+}
+
+pub struct TypedStore<'a, T> {
+    store: &'a mut EventStore,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> TypedStore<'a, T>
+where
+    T: StoredAggregate,
+    T::EventType: EventCodec,
+    <T::IdentifiableType as Identifiable>::IdType: Display,
+{
+    fn stream(&self) -> impl '_ + Iterator<Item = &StoredEvent> {
+        self.store
+            .events
+            .iter()
+            .filter(|x| x.stream_type == T::STREAM_TYPE)
+    }
+
+    pub fn load(&self, id: &Id<T::IdentifiableType>) -> Result<T> {
+        let stream_id = id.to_string();
+        let events = self
+            .stream()
+            .filter(|x| x.stream_id == stream_id)
+            .map(|x| self.store.decode(x))
+            .collect::<Result<Vec<_>>>()?;
+        T::load(events)
+    }
+
+    pub fn load_all(&self) -> Result<Vec<T>>
+    where
+        T::EventType: KindOfEvent,
+    {
+        let events = self
+            .stream()
+            .map(|x| Ok((x.stream_id.clone(), self.store.decode(x)?)))
+            .collect::<Result<Vec<_>>>()?;
+        T::load_many(events)
+    }
+
+    /// Appends pending changes of the aggregate to the global feed and
+    /// acknowledges them
+    pub fn save(&mut self, root: &mut T) -> Result<usize> {
+        let token = root.pending_changes();
+        let mut events = Vec::new();
+        let count = root.stream_to(&mut events).unwrap_or_else(|e| match e {});
+        let stream_id = root.get_id().to_string();
+        for e in events {
+            self.store.append(
+                T::STREAM_TYPE,
+                stream_id.clone(),
+                T::EventType::VERSION,
+                e.encode(),
+            );
+        }
+        root.acknowledge(token);
+        Ok(count)
+    }
+
+    /// Appends payload written with an older schema version, e.g. from
+    /// archived fixtures
+    pub fn append_archived(&mut self, id: &Id<T::IdentifiableType>, version: u32, payload: &str) {
+        self.store
+            .append(T::STREAM_TYPE, id.to_string(), version, payload.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changable::Changable;
+    use crate::changes::{FullChange, FullChanges, Record};
+    use crate::historic::Historic;
+    use crate::invariants::Invariants;
+    use crate::result::{Error, ErrorKind};
+    use crate::streamable::EventKind;
+    use crate::streaming::Stream;
+    use crate::streaming_strategies::CloneRedoStreamingStrategy;
+    use crate::undoable::Undoable;
+    use crate::upcasting::UpcastFn;
+    use pretty_assertions::assert_eq;
+    use std::mem;
+    use std::result::Result as StdResult;
+    use std::str::FromStr;
+
+    /// Names the kind of `Doc` so that several aggregate types share code
+    trait Kind: 'static {
+        const NAME: &'static str;
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Invoices;
+
+    impl Kind for Invoices {
+        const NAME: &'static str = "invoice";
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Notes;
+
+    impl Kind for Notes {
+        const NAME: &'static str = "note";
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Bills;
+
+    impl Kind for Bills {
+        const NAME: &'static str = "invoice";
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum DocEvent {
+        Created(u32),
+        Written(String),
+    }
+
+    #[derive(Debug, Default)]
+    struct Doc<K> {
+        id: u32,
+        text: String,
+        changes: Record<FullChange<DocEvent>>,
+        kind: PhantomData<K>,
+    }
+
+    impl<K> PartialEq for Doc<K> {
+        fn eq(&self, other: &Self) -> bool {
+            (self.id, &self.text) == (other.id, &other.text)
+        }
+    }
+
+    impl<K: Kind + Default> Doc<K> {
+        fn written(id: u32, text: &str) -> Self {
+            let mut result = Self::default();
+            let changes: FullChanges<_> =
+                result.applied_many(vec![DocEvent::Created(id), DocEvent::Written(text.into())]);
+            result.changes.extend(changes);
+            result
+        }
+    }
+
+    impl EventCodec for DocEvent {
+        /// v1 used `=` separator and `text` tag, v2 quoted the text
+        const VERSION: u32 = 3;
+
+        fn encode(&self) -> String {
+            match self {
+                DocEvent::Created(id) => format!("created:{}", id),
+                DocEvent::Written(text) => format!("written:{}", text),
+            }
+        }
+
+        fn decode(payload: &str) -> Result<Self> {
+            match payload.split_once(':') {
+                Some(("created", id)) => u32::from_str(id)
+                    .map(DocEvent::Created)
+                    .map_err(|e| Error::inconsistent_event(e.to_string())),
+                Some(("written", text)) => Ok(DocEvent::Written(text.to_string())),
+                _ => Err(Error::inconsistent_event(payload)),
+            }
+        }
+    }
+
+    impl<K: Kind> Identifiable for Doc<K> {
+        type IdType = u32;
+        const PREFIX: &'static str = K::NAME;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.id)
+        }
+    }
+
+    impl<K> Historic for Doc<K> {
+        type EventType = DocEvent;
+    }
+
+    impl KindOfEvent for DocEvent {
+        fn kind_of_event(&self) -> EventKind {
+            match self {
+                DocEvent::Created(_) => EventKind::Creation,
+                DocEvent::Written(_) => EventKind::Other,
+            }
+        }
+    }
+
+    impl<K> Changable for Doc<K> {
+        fn apply(&mut self, event: DocEvent) -> DocEvent {
+            match event {
+                DocEvent::Created(id) => DocEvent::Created(mem::replace(&mut self.id, id)),
+                DocEvent::Written(text) => DocEvent::Written(mem::replace(&mut self.text, text)),
+            }
+        }
+    }
+
+    impl<K> Invariants for Doc<K> {}
+
+    impl<K> Undoable for Doc<K> {
+        fn changes_mut(&mut self) -> &mut Record<FullChange<DocEvent>> {
+            &mut self.changes
+        }
+    }
+
+    impl<K> Streamable for Doc<K> {
+        fn stream_to<S>(&mut self, stream: &mut S) -> StdResult<usize, S::Error>
+        where
+            S: Stream<DocEvent>,
+        {
+            CloneRedoStreamingStrategy::new(self).stream_to(stream)
+        }
+    }
+
+    impl<K: Kind + Default> StoredAggregate for Doc<K> {
+        const STREAM_TYPE: &'static str = K::NAME;
+    }
+
+    type Invoice = Doc<Invoices>;
+    type Note = Doc<Notes>;
+    type Bill = Doc<Bills>;
+
+    fn setup() -> EventStore {
+        let mut sut = EventStore::new();
+        sut.of::<Invoice>()
+            .unwrap()
+            .save(&mut Invoice::written(1, "due"))
+            .unwrap();
+        sut.of::<Note>()
+            .unwrap()
+            .save(&mut Note::written(1, "call back"))
+            .unwrap();
+        sut
+    }
+
+    #[test]
+    fn should_keep_streams_of_types_apart() {
+        let mut sut = setup();
+
+        let invoice = sut.of::<Invoice>().unwrap().load(&Id::new(1)).unwrap();
+        let notes = sut.of::<Note>().unwrap().load_all().unwrap();
+
+        assert_eq!(invoice, Invoice::written(1, "due"));
+        assert_eq!(notes, vec![Note::written(1, "call back")]);
+    }
+
+    #[test]
+    fn should_reject_other_type_with_same_stream_type() {
+        let mut sut = setup();
+
+        let err = sut.of::<Bill>().map(|_| ()).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Validation);
+        assert!(err.to_string().contains("Stream type invoice of"));
+        assert!(sut.of::<Invoice>().is_ok());
+    }
+
+    #[test]
+    fn should_order_events_of_all_types_in_global_feed() {
+        let sut = setup();
+
+        let feed: Vec<_> = sut
+            .feed(1)
+            .map(|x| {
+                (
+                    x.sequence,
+                    x.stream_type,
+                    x.stream_id.as_str(),
+                    x.payload.as_str(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            feed,
+            vec![
+                (2, "invoice", "invoice_1", "written:due"),
+                (3, "note", "note_1", "created:1"),
+                (4, "note", "note_1", "written:call back"),
+            ]
+        );
+        assert_eq!(Id::<Note>::from_str(feed[1].2).unwrap(), Id::new(1));
+    }
+
+    #[test]
+    fn should_save_only_pending_changes() {
+        let mut sut = setup();
+        let mut invoice = sut.of::<Invoice>().unwrap().load(&Id::new(1)).unwrap();
+        let changes: FullChanges<_> = invoice.applied(DocEvent::Written("paid".into()));
+        invoice.changes.extend(changes);
+
+        let count = sut.of::<Invoice>().unwrap().save(&mut invoice).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(sut.last_sequence(), 5);
+        assert_eq!(
+            sut.of::<Invoice>().unwrap().load(&Id::new(1)).unwrap().text,
+            "paid"
+        );
+    }
+
+    fn upcasters() -> UpcasterChain {
+        UpcasterChain::new()
+            .with(UpcastFn::new("invoice", 1, |x| {
+                Ok(x.replacen('=', ":", 1).replacen("text:", "written:", 1))
+            }))
+            .with(UpcastFn::new("invoice", 2, |x| Ok(x.replace('"', ""))))
+    }
+
+    #[test]
+    fn should_upcast_old_payloads_on_load() {
+        let mut sut = EventStore::with_upcasters(upcasters());
+        let id = Id::new(1);
+        sut.of::<Invoice>()
+            .unwrap()
+            .append_archived(&id, 1, "created=1");
+        sut.of::<Invoice>()
+            .unwrap()
+            .append_archived(&id, 2, "written:\"due\"");
+        let mut invoice = sut.of::<Invoice>().unwrap().load(&id).unwrap();
+        let changes: FullChanges<_> = invoice.applied(DocEvent::Written("paid".into()));
+        invoice.changes.extend(changes);
+
+        sut.of::<Invoice>().unwrap().save(&mut invoice).unwrap();
+
+        assert_eq!(
+            sut.of::<Invoice>().unwrap().load_all().unwrap(),
+            vec![Invoice::written(1, "paid")]
+        );
+        assert_eq!(sut.feed(2).map(|x| x.version).collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn should_replay_archived_fixture() {
+        let fixture = [
+            (1, "created=7"),
+            (1, "text=\"draft\""),
+            (3, "written:final"),
+        ];
+
+        let replayed: Invoice = replay_fixture(&upcasters(), &fixture).unwrap();
+
+        assert_eq!(replayed, Invoice::written(7, "final"));
+    }
+
+    #[test]
+    fn should_fail_to_load_payload_without_upcaster() {
+        let mut sut = EventStore::new();
+        sut.of::<Note>()
+            .unwrap()
+            .append_archived(&Id::new(1), 1, "created=1");
+
+        let err = sut.of::<Note>().unwrap().load(&Id::new(1)).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Inconsistent event: No upcaster for note v1"
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use crate::identifiable::{Id, Identifiable};
+use crate::result::{Error, Result};
+use std::any;
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hasher};
+use std::marker::PhantomData;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Allocates ids for new entities of type `T`
+pub trait IdGenerator<T: Identifiable> {
+    fn next_id(&mut self) -> Result<Id<T>>;
+}
+
+/// Monotonic per-type sequence 1, 2, 3, ...
+pub struct Sequence<T> {
+    next: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Sequence<T> {
+    pub fn new() -> Self {
+        Self::starting_at(1)
+    }
+
+    /// Continues sequence, e.g. after the highest id already stored
+    pub fn starting_at(next: u64) -> Self {
+        Self {
+            next,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for Sequence<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IdGenerator<T> for Sequence<T>
+where
+    T: Identifiable,
+    T::IdType: TryFrom<u64>,
+{
+    /// Fails when the sequence does not fit into the id type
+    fn next_id(&mut self) -> Result<Id<T>> {
+        let raw = T::IdType::try_from(self.next).map_err(|_| {
+            Error::from_text(format!("{} sequence is exhausted", any::type_name::<T>()))
+        })?;
+        self.next += 1;
+        Ok(Id::new(raw))
+    }
+}
+
+/// Random 128-bit id seeded by the standard library's per-process randomness
+pub struct Random128<T> {
+    state: RandomState,
+    counter: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Random128<T> {
+    pub fn new() -> Self {
+        Self {
+            state: RandomState::new(),
+            counter: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.counter += 1;
+        let mut hasher = self.state.build_hasher();
+        hasher.write_u64(self.counter);
+        hasher.finish()
+    }
+
+    fn next_u128(&mut self) -> u128 {
+        (u128::from(self.next_u64()) << 64) | u128::from(self.next_u64())
+    }
+}
+
+impl<T> Default for Random128<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IdGenerator<T> for Random128<T>
+where
+    T: Identifiable,
+    T::IdType: From<u128>,
+{
+    fn next_id(&mut self) -> Result<Id<T>> {
+        Ok(Id::new(self.next_u128().into()))
+    }
+}
+
+/// Sortable 128-bit id: 48 bits of milliseconds since Unix epoch followed
+/// by 80 random bits. Ids generated within the same millisecond keep
+/// increasing, so the order of generation is preserved.
+pub struct TimeOrdered<T> {
+    random: Random128<T>,
+    last: u128,
+}
+
+const RANDOM_BITS: u32 = 80;
+
+impl<T> TimeOrdered<T> {
+    pub fn new() -> Self {
+        Self {
+            random: Random128::new(),
+            last: 0,
+        }
+    }
+
+    fn next_u128(&mut self, millis: u64) -> u128 {
+        let random = self.random.next_u128() & ((1 << RANDOM_BITS) - 1);
+        let candidate = (u128::from(millis) << RANDOM_BITS) | random;
+        self.last = if candidate > self.last {
+            candidate
+        } else {
+            self.last + 1
+        };
+        self.last
+    }
+}
+
+impl<T> Default for TimeOrdered<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IdGenerator<T> for TimeOrdered<T>
+where
+    T: Identifiable,
+    T::IdType: From<u128>,
+{
+    fn next_id(&mut self) -> Result<Id<T>> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        Ok(Id::new(self.next_u128(millis).into()))
+    }
+}
+
+/// Deterministic pseudo-random ids for tests (splitmix64)
+pub struct Seeded<T> {
+    state: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Seeded<T> {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+            _marker: PhantomData,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl<T> IdGenerator<T> for Seeded<T>
+where
+    T: Identifiable,
+    T::IdType: From<u64>,
+{
+    fn next_id(&mut self) -> Result<Id<T>> {
+        Ok(Id::new(self.next_u64().into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashSet;
+
+    struct Small(u8);
+
+    impl Identifiable for Small {
+        type IdType = u8;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.0)
+        }
+    }
+
+    struct Wide(u128);
+
+    impl Identifiable for Wide {
+        type IdType = u128;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.0)
+        }
+    }
+
+    struct Plain(u64);
+
+    impl Identifiable for Plain {
+        type IdType = u64;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.0)
+        }
+    }
+
+    fn take<T: Identifiable, G: IdGenerator<T>>(sut: &mut G, n: usize) -> Vec<T::IdType>
+    where
+        T::IdType: Clone,
+    {
+        (0..n)
+            .map(|_| sut.next_id().unwrap().raw().clone())
+            .collect()
+    }
+
+    #[test]
+    fn should_generate_sequence() {
+        let mut sut = Sequence::<Small>::starting_at(254);
+
+        assert_eq!(take(&mut sut, 2), vec![254, 255]);
+    }
+
+    #[test]
+    fn should_fail_when_sequence_overflows_id_type() {
+        let mut sut = Sequence::<Small>::starting_at(255);
+        sut.next_id().unwrap();
+
+        let exhausted = sut.next_id().unwrap_err();
+
+        assert!(exhausted
+            .to_string()
+            .ends_with("Small sequence is exhausted"));
+        assert!(sut.next_id().is_err());
+    }
+
+    #[test]
+    fn should_generate_distinct_random_ids() {
+        let mut sut = Random128::<Wide>::new();
+
+        let ids: HashSet<_> = take(&mut sut, 1000).into_iter().collect();
+
+        assert_eq!(ids.len(), 1000);
+    }
+
+    #[test]
+    fn should_keep_order_of_time_ordered_ids() {
+        let mut sut = TimeOrdered::<Wide>::new();
+
+        let ids = take(&mut sut, 1000);
+
+        let mut sorted = ids.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(ids, sorted);
+    }
+
+    #[test]
+    fn should_keep_order_within_same_millisecond() {
+        let mut sut = TimeOrdered::<Wide>::new();
+
+        let first = sut.next_u128(7);
+        let second = sut.next_u128(7);
+
+        assert!(first < second);
+        assert_eq!(second >> RANDOM_BITS, 7);
+    }
+
+    #[test]
+    fn should_repeat_ids_for_same_seed() {
+        let first = take(&mut Seeded::<Plain>::new(42), 5);
+        let second = take(&mut Seeded::<Plain>::new(42), 5);
+        let other = take(&mut Seeded::<Plain>::new(43), 5);
+
+        assert_eq!(first, second);
+        assert!(first != other);
+    }
+}
@@ -1,13 +1,19 @@
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 
 use crate::contextual::Contextual;
-use std::fmt::{Debug, Formatter};
+use crate::result::ParseIdError;
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
+use std::str::FromStr;
 
 pub trait Identifiable: Sized {
     type IdType: Eq;
 
+    /// Type tag of `Id` text form, e.g. `order` for `order_42`.
+    /// Empty prefix means the raw value only.
+    const PREFIX: &'static str = "";
+
     fn id(&self) -> Id<Self>;
 }
 
@@ -93,14 +99,91 @@ impl<T: Identifiable> Id<T> {
         &self.id
     }
 
+    pub fn into_raw(self) -> T::IdType {
+        self.id
+    }
+
     pub fn convert<U>(self) -> Id<U>
     where
         U: Identifiable<IdType = T::IdType>,
     {
         Id::new(self.id)
     }
+
+    pub fn component<const N: usize>(&self) -> &<T::IdType as Component<N>>::Type
+    where
+        T::IdType: Component<N>,
+    {
+        self.id.component()
+    }
+
+    pub fn first(&self) -> &<T::IdType as Component<0>>::Type
+    where
+        T::IdType: Component<0>,
+    {
+        self.component::<0>()
+    }
+
+    pub fn second(&self) -> &<T::IdType as Component<1>>::Type
+    where
+        T::IdType: Component<1>,
+    {
+        self.component::<1>()
+    }
+
+    pub fn third(&self) -> &<T::IdType as Component<2>>::Type
+    where
+        T::IdType: Component<2>,
+    {
+        self.component::<2>()
+    }
+
+    pub fn fourth(&self) -> &<T::IdType as Component<3>>::Type
+    where
+        T::IdType: Component<3>,
+    {
+        self.component::<3>()
+    }
+}
+
+/// Part `N` of a composite (tuple) id
+pub trait Component<const N: usize> {
+    type Type;
+
+    fn component(&self) -> &Self::Type;
 }
 
+macro_rules! composite_id {
+    ($($n:tt: $t:ident),+) => {
+        composite_id!(@components ($($t),+) $($n: $t),+);
+
+        impl<T, $($t),+> From<($($t,)+)> for Id<T>
+        where
+            T: Identifiable<IdType = ($($t,)+)>,
+        {
+            fn from(value: ($($t,)+)) -> Self {
+                Id::new(value)
+            }
+        }
+    };
+    (@components $all:tt $($n:tt: $t:ident),+) => {
+        $(composite_id!(@component $all $n: $t);)+
+    };
+    (@component ($($all:ident),+) $n:tt: $t:ident) => {
+        impl<$($all),+> Component<$n> for ($($all,)+) {
+            type Type = $t;
+
+            fn component(&self) -> &Self::Type {
+                &self.$n
+            }
+        }
+    };
+}
+
+composite_id!(0: A, 1: B);
+composite_id!(0: A, 1: B, 2: C);
+composite_id!(0: A, 1: B, 2: C, 3: D);
+
 impl<T: Identifiable> Copy for Id<T>
 where
     Self: Clone,
@@ -126,6 +209,47 @@ where
     }
 }
 
+impl<T: Identifiable> Display for Id<T>
+where
+    T::IdType: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !T::PREFIX.is_empty() {
+            write!(f, "{}_", T::PREFIX)?;
+        }
+        self.id.fmt(f)
+    }
+}
+
+impl<T: Identifiable> FromStr for Id<T>
+where
+    T::IdType: FromStr,
+    <T::IdType as FromStr>::Err: Display,
+{
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw = if T::PREFIX.is_empty() {
+            s
+        } else {
+            s.strip_prefix(T::PREFIX)
+                .and_then(|rest| rest.strip_prefix('_'))
+                .ok_or_else(|| ParseIdError::WrongPrefix {
+                    expected: T::PREFIX,
+                    found: s.to_string(),
+                })?
+        };
+        raw.parse()
+            .map(Id::new)
+            .map_err(
+                |e: <T::IdType as FromStr>::Err| ParseIdError::InvalidValue {
+                    value: raw.to_string(),
+                    reason: e.to_string(),
+                },
+            )
+    }
+}
+
 impl<T: Identifiable> Ord for Id<T>
 where
     T::IdType: Ord,
@@ -151,3 +275,163 @@ impl<T: Identifiable> PartialEq for Id<T> {
         self.id.eq(&y.id)
     }
 }
+
+/// Id of owned entity which is unique across all owners
+pub struct QualifiedId<T: Identifiable + Owned> {
+    owner: Id<T::OwnerType>,
+    id: Id<T>,
+}
+
+impl<T: Identifiable + Owned> QualifiedId<T> {
+    pub fn new(owner: Id<T::OwnerType>, id: Id<T>) -> Self {
+        Self { owner, id }
+    }
+
+    pub fn owner(&self) -> &Id<T::OwnerType> {
+        &self.owner
+    }
+
+    pub fn id(&self) -> &Id<T> {
+        &self.id
+    }
+}
+
+impl<T: Identifiable + Owned> Clone for QualifiedId<T>
+where
+    Id<T::OwnerType>: Clone,
+    Id<T>: Clone,
+{
+    fn clone(&self) -> Self {
+        Self::new(self.owner.clone(), self.id.clone())
+    }
+}
+
+impl<T: Identifiable + Owned> Copy for QualifiedId<T>
+where
+    Self: Clone,
+    Id<T::OwnerType>: Copy,
+    Id<T>: Copy,
+{
+}
+
+impl<T: Identifiable + Owned> Debug for QualifiedId<T>
+where
+    Id<T::OwnerType>: Debug,
+    Id<T>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("QualifiedId")
+            .field(&self.owner)
+            .field(&self.id)
+            .finish()
+    }
+}
+
+impl<T: Identifiable + Owned> Hash for QualifiedId<T>
+where
+    Id<T::OwnerType>: Hash,
+    Id<T>: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.owner.hash(state);
+        self.id.hash(state);
+    }
+}
+
+impl<T: Identifiable + Owned> Eq for QualifiedId<T> {}
+
+impl<T: Identifiable + Owned> PartialEq for QualifiedId<T> {
+    fn eq(&self, y: &Self) -> bool {
+        self.owner == y.owner && self.id == y.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    struct Order;
+
+    impl Identifiable for Order {
+        type IdType = i32;
+
+        const PREFIX: &'static str = "order";
+
+        fn id(&self) -> Id<Self> {
+            Id::new(42)
+        }
+    }
+
+    struct OrderLine {
+        order: i32,
+        line_no: u16,
+    }
+
+    impl Identifiable for OrderLine {
+        type IdType = (i32, u16);
+
+        fn id(&self) -> Id<Self> {
+            (self.order, self.line_no).into()
+        }
+    }
+
+    impl Owned for OrderLine {
+        type OwnerType = Order;
+    }
+
+    #[test]
+    fn should_access_components_of_composite_id() {
+        let line = OrderLine {
+            order: 42,
+            line_no: 3,
+        };
+
+        let id = line.id();
+
+        assert_eq!((id.first(), id.second()), (&42, &3));
+        assert_eq!(id.into_raw(), (42, 3));
+    }
+
+    #[test]
+    fn should_distinguish_same_child_id_of_different_owners() {
+        let id: Id<OrderLine> = (1, 1).into();
+
+        let first = QualifiedId::new(Id::new(1), id);
+        let second = QualifiedId::new(Id::new(2), id);
+
+        assert!(first != second);
+        assert_eq!(first, QualifiedId::new(Id::new(1), (1, 1).into()));
+    }
+
+    #[test]
+    fn should_display_prefixed_id() {
+        let id: Id<Order> = Id::new(42);
+
+        assert_eq!(id.to_string(), "order_42");
+        assert_eq!("order_42".parse(), Ok(id));
+    }
+
+    #[test]
+    fn should_reject_id_of_other_type() {
+        let parsed = "customer_42".parse::<Id<Order>>();
+
+        assert_eq!(
+            parsed,
+            Err(ParseIdError::WrongPrefix {
+                expected: "order",
+                found: "customer_42".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_raw_value() {
+        let parsed = "order_x".parse::<Id<Order>>();
+
+        assert!(matches!(
+            parsed,
+            Err(ParseIdError::InvalidValue { value, .. }) if value == "x"
+        ));
+    }
+}
@@ -0,0 +1,22 @@
+use crate::result::Result;
+use std::rc::Rc;
+
+/// Aggregate-wide consistency rules evaluated by `Atomic::commit`.
+/// Failed check rolls the transaction back.
+pub trait Invariants {
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Per-entity validation used by `Master` and `Details` on `apply`
+/// of created and updated entities.
+pub type Validator<T> = Rc<dyn Fn(&T) -> Result<()>>;
+
+/// Ambient context which also validates entities applied in it by
+/// `Master` and `Details`, e.g. that `updated_at` is not ahead of its clock
+pub trait ValidationContext<T> {
+    fn validate(&mut self, _entity: &T) -> Result<()> {
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
-use itertools::{EitherOrBoth, Itertools};
+use itertools::EitherOrBoth;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::iter::Peekable;
 
 use crate::identifiable::{GetId, Id, Identifiable};
 
@@ -12,6 +16,8 @@ pub trait SingleReference<OtherType: Identifiable> {
     fn reference(&self) -> Id<OtherType>;
 }
 
+/// Inner merge join. Both inputs should be sorted, refs by reference and
+/// defs by unique id; unsorted input is detected by debug assertions.
 pub fn join<'a, R, D, T, Refs, Defs>(
     refs: Refs,
     defs: Defs,
@@ -20,16 +26,343 @@ where
     Refs: IntoIterator<Item = &'a R>,
     Defs: IntoIterator<Item = &'a D>,
     R: 'a + SingleReference<T>,
-    D: 'a + GetId<IdentifiableType=T>,
+    D: 'a + GetId<IdentifiableType = T>,
     T: Identifiable,
     Id<T>: Ord,
 {
+    outer_join(refs, defs).filter_map(|e| match e {
+        EitherOrBoth::Both(x, y) => Some((x, y)),
+        _ => None,
+    })
+}
+
+/// Every ref either with its def (`Both`) or without it (`Left`).
+/// Same input requirements as `join`.
+pub fn left_join<'a, R, D, T, Refs, Defs>(
+    refs: Refs,
+    defs: Defs,
+) -> impl Iterator<Item = EitherOrBoth<&'a R, &'a D>>
+where
+    Refs: IntoIterator<Item = &'a R>,
+    Defs: IntoIterator<Item = &'a D>,
+    R: 'a + SingleReference<T>,
+    D: 'a + GetId<IdentifiableType = T>,
+    T: Identifiable,
+    Id<T>: Ord,
+{
+    outer_join(refs, defs).filter(|e| !matches!(e, EitherOrBoth::Right(_)))
+}
+
+/// Like `left_join` plus unreferenced defs as `Right`.
+/// Same input requirements as `join`.
+pub fn outer_join<'a, R, D, T, Refs, Defs>(
+    refs: Refs,
+    defs: Defs,
+) -> MergeJoin<'a, R, D, T, Refs::IntoIter, Defs::IntoIter>
+where
+    Refs: IntoIterator<Item = &'a R>,
+    Defs: IntoIterator<Item = &'a D>,
+    R: 'a + SingleReference<T>,
+    D: 'a + GetId<IdentifiableType = T>,
+    T: Identifiable,
+    Id<T>: Ord,
+{
+    MergeJoin {
+        refs: refs.into_iter().peekable(),
+        defs: defs.into_iter().peekable(),
+        def_matched: false,
+        last_ref: None,
+        last_def: None,
+    }
+}
+
+/// Inner join of refs in any order; result follows the order of refs
+pub fn hash_join<'a, R, D, T, Refs, Defs>(
+    refs: Refs,
+    defs: Defs,
+) -> impl Iterator<Item = (&'a R, &'a D)>
+where
+    Refs: IntoIterator<Item = &'a R>,
+    Defs: IntoIterator<Item = &'a D>,
+    R: 'a + SingleReference<T>,
+    D: 'a + GetId<IdentifiableType = T>,
+    T: Identifiable,
+    Id<T>: Hash,
+{
+    let by_id = index_by_id(defs);
     refs.into_iter()
-        .merge_join_by(defs, |x, y| {
-            Ord::cmp(&x.reference(), &y.get_id())
-        })
-        .filter_map(|e| match e {
-            EitherOrBoth::Both(x, y) => Some((x, y)),
-            _ => None,
-        })
+        .filter_map(move |r| by_id.get(&r.reference()).map(|&d| (r, d)))
+}
+
+/// Joins each row with every def it references via `ManyReferences`,
+/// in order of rows and their references
+pub fn join_many<'a, R, D, T, Rows, Defs>(
+    rows: Rows,
+    defs: Defs,
+) -> impl Iterator<Item = (&'a R, &'a D)>
+where
+    Rows: IntoIterator<Item = &'a R>,
+    Defs: IntoIterator<Item = &'a D>,
+    R: 'a + ManyReferences<T>,
+    D: 'a + GetId<IdentifiableType = T>,
+    T: Identifiable,
+    Id<T>: Hash,
+{
+    let by_id = index_by_id(defs);
+    rows.into_iter().flat_map(move |r| {
+        r.references()
+            .filter_map(|id| by_id.get(&id).copied())
+            .map(move |d| (r, d))
+            .collect::<Vec<_>>()
+    })
+}
+
+/// Each def with rows referencing it, in order of defs and rows
+pub fn group_join<'a, R, D, T, Defs, Refs>(defs: Defs, refs: Refs) -> Vec<(&'a D, Vec<&'a R>)>
+where
+    Defs: IntoIterator<Item = &'a D>,
+    Refs: IntoIterator<Item = &'a R>,
+    R: 'a + SingleReference<T>,
+    D: 'a + GetId<IdentifiableType = T>,
+    T: Identifiable,
+    Id<T>: Hash,
+{
+    let mut groups: HashMap<Id<T>, Vec<&'a R>> = HashMap::new();
+    for r in refs {
+        groups.entry(r.reference()).or_default().push(r);
+    }
+    defs.into_iter()
+        .map(|d| (d, groups.remove(&d.get_id()).unwrap_or_default()))
+        .collect()
+}
+
+fn index_by_id<'a, D, T, Defs>(defs: Defs) -> HashMap<Id<T>, &'a D>
+where
+    Defs: IntoIterator<Item = &'a D>,
+    D: 'a + GetId<IdentifiableType = T>,
+    T: Identifiable,
+    Id<T>: Hash,
+{
+    defs.into_iter().map(|d| (d.get_id(), d)).collect()
+}
+
+/// Merge join where many refs may point to the same def
+pub struct MergeJoin<'a, R, D, T, IR, ID>
+where
+    IR: Iterator<Item = &'a R>,
+    ID: Iterator<Item = &'a D>,
+    R: 'a,
+    D: 'a,
+    T: Identifiable,
+{
+    refs: Peekable<IR>,
+    defs: Peekable<ID>,
+    def_matched: bool,
+    last_ref: Option<Id<T>>,
+    last_def: Option<Id<T>>,
+}
+
+impl<'a, R, D, T, IR, ID> MergeJoin<'a, R, D, T, IR, ID>
+where
+    IR: Iterator<Item = &'a R>,
+    ID: Iterator<Item = &'a D>,
+    R: 'a + SingleReference<T>,
+    D: 'a + GetId<IdentifiableType = T>,
+    T: Identifiable,
+    Id<T>: Ord,
+{
+    fn next_ref(&mut self) -> Option<&'a R> {
+        let r = self.refs.next()?;
+        if cfg!(debug_assertions) {
+            let key = r.reference();
+            debug_assert!(
+                self.last_ref.iter().all(|last| last <= &key),
+                "Refs should be sorted by reference"
+            );
+            self.last_ref = Some(key);
+        }
+        Some(r)
+    }
+
+    fn next_def(&mut self) -> Option<&'a D> {
+        let d = self.defs.next()?;
+        if cfg!(debug_assertions) {
+            let key = d.get_id();
+            debug_assert!(
+                self.last_def.iter().all(|last| last < &key),
+                "Defs should be sorted by unique id"
+            );
+            self.last_def = Some(key);
+        }
+        Some(d)
+    }
+}
+
+impl<'a, R, D, T, IR, ID> Iterator for MergeJoin<'a, R, D, T, IR, ID>
+where
+    IR: Iterator<Item = &'a R>,
+    ID: Iterator<Item = &'a D>,
+    R: 'a + SingleReference<T>,
+    D: 'a + GetId<IdentifiableType = T>,
+    T: Identifiable,
+    Id<T>: Ord,
+{
+    type Item = EitherOrBoth<&'a R, &'a D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let order = match (self.refs.peek(), self.defs.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(r), Some(d)) => r.reference().cmp(&d.get_id()),
+            };
+            match order {
+                Ordering::Less => return self.next_ref().map(EitherOrBoth::Left),
+                Ordering::Equal => {
+                    self.def_matched = true;
+                    let d = *self.defs.peek()?;
+                    return self.next_ref().map(|r| EitherOrBoth::Both(r, d));
+                }
+                Ordering::Greater => {
+                    let d = self.next_def()?;
+                    if !std::mem::take(&mut self.def_matched) {
+                        return Some(EitherOrBoth::Right(d));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::vec;
+    use EitherOrBoth::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Customer(i32);
+
+    impl Identifiable for Customer {
+        type IdType = i32;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.0)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Order(i32, i32);
+
+    impl SingleReference<Customer> for Order {
+        fn reference(&self) -> Id<Customer> {
+            Id::new(self.1)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Meeting(Vec<i32>);
+
+    impl ManyReferences<Customer> for Meeting {
+        type Iter = vec::IntoIter<Id<Customer>>;
+
+        fn references(&self) -> Self::Iter {
+            let ids: Vec<_> = self.0.iter().copied().map(Id::new).collect();
+            ids.into_iter()
+        }
+    }
+
+    fn customers() -> Vec<Customer> {
+        vec![Customer(1), Customer(2), Customer(3)]
+    }
+
+    fn orders() -> Vec<Order> {
+        vec![Order(10, 1), Order(11, 1), Order(12, 3), Order(13, 4)]
+    }
+
+    fn pairs<'a>(joined: impl IntoIterator<Item = (&'a Order, &'a Customer)>) -> Vec<(i32, i32)> {
+        joined.into_iter().map(|(o, c)| (o.0, c.0)).collect()
+    }
+
+    #[test]
+    fn should_join_many_refs_to_same_def() {
+        let (orders, customers) = (orders(), customers());
+
+        let joined = pairs(join(&orders, &customers));
+
+        assert_eq!(joined, vec![(10, 1), (11, 1), (12, 3)]);
+    }
+
+    #[test]
+    fn should_outer_join() {
+        let (orders, customers) = (orders(), customers());
+
+        let joined: Vec<_> = outer_join(&orders, &customers)
+            .map(|e| e.map_any(|o| o.0, |c| c.0))
+            .collect();
+
+        assert_eq!(
+            joined,
+            vec![Both(10, 1), Both(11, 1), Right(2), Both(12, 3), Left(13)]
+        );
+    }
+
+    #[test]
+    fn should_left_join() {
+        let (orders, customers) = (orders(), customers());
+
+        let joined: Vec<_> = left_join(&orders, &customers)
+            .map(|e| e.map_any(|o| o.0, |c| c.0))
+            .collect();
+
+        assert_eq!(
+            joined,
+            vec![Both(10, 1), Both(11, 1), Both(12, 3), Left(13)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Refs should be sorted by reference")]
+    fn should_detect_unsorted_refs() {
+        let orders = vec![Order(12, 3), Order(10, 1)];
+        let customers = customers();
+
+        join(&orders, &customers).into_iter().for_each(drop);
+    }
+
+    #[test]
+    fn should_hash_join_unsorted_input() {
+        let orders = vec![Order(12, 3), Order(13, 4), Order(10, 1)];
+        let customers = vec![Customer(3), Customer(1)];
+
+        let joined = pairs(hash_join(&orders, &customers));
+
+        assert_eq!(joined, vec![(12, 3), (10, 1)]);
+    }
+
+    #[test]
+    fn should_join_many_references() {
+        let meetings = vec![Meeting(vec![3, 1]), Meeting(vec![4])];
+        let customers = customers();
+
+        let joined: Vec<_> = join_many(&meetings, &customers)
+            .map(|(m, c)| (m.0.len(), c.0))
+            .collect();
+
+        assert_eq!(joined, vec![(2, 3), (2, 1)]);
+    }
+
+    #[test]
+    fn should_group_refs_by_def() {
+        let (orders, customers) = (orders(), customers());
+
+        let grouped: Vec<_> = group_join(&customers, &orders)
+            .into_iter()
+            .map(|(c, os)| (c.0, os.iter().map(|o| o.0).collect::<Vec<_>>()))
+            .collect();
+
+        assert_eq!(grouped, vec![(1, vec![10, 11]), (2, vec![]), (3, vec![12])]);
+    }
 }
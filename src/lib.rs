@@ -1,30 +1,58 @@
+#[cfg(feature = "async")]
+mod async_streaming;
 mod changable;
 mod change_abs;
 mod changes;
 mod contextual;
 mod details;
+mod domain_events;
+mod event_store;
 mod historic;
+mod id_generation;
 mod identifiable;
+mod invariants;
+pub mod joins;
 mod master;
+mod master_detail;
+mod nested_details;
+mod query;
+mod references;
+mod relay;
 pub mod result;
 mod storage;
 mod streamable;
 mod streaming;
 mod streaming_strategies;
+mod tenancy;
 mod test_utils;
+mod transactional_stream;
 mod undoable;
-pub mod joins;
+mod upcasting;
 
+#[cfg(feature = "async")]
+pub use async_streaming::*;
 pub use changable::*;
 pub use changes::*;
 pub use contextual::*;
 pub use details::*;
+pub use domain_events::*;
+pub use event_store::*;
 pub use historic::*;
+pub use id_generation::*;
 pub use identifiable::*;
+pub use invariants::*;
 pub use master::*;
+pub use master_detail::*;
+pub use nested_details::*;
+pub use query::*;
+pub use references::*;
+pub use relay::*;
 pub use result::*;
 pub use storage::*;
 pub use streamable::*;
 pub use streaming::*;
 pub use streaming_strategies::*;
+pub use tenancy::*;
+pub use transactional_stream::*;
 pub use undoable::*;
+pub use upcasting::*;
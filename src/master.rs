@@ -1,12 +1,17 @@
-use crate::{changable::Changable, change_abs::NoopChange};
+use crate::{
+    changable::{Changable, ChangableInContext},
+    change_abs::NoopChange,
+};
 use crate::change_abs::AppliedChange;
 use crate::historic::Historic;
 use crate::identifiable::*;
-use crate::result::NotFound;
+use crate::invariants::{ValidationContext, Validator};
+use crate::result::{Error, NotFound, Result};
 use crate::FullChanges;
 use std::cmp::{Eq, PartialEq};
 use std::fmt;
 use std::marker;
+use std::rc::Rc;
 use std::result::Result as StdResult;
 use MasterEvent::*;
 
@@ -66,6 +71,10 @@ where
 
 pub struct Master<T: GetId, C = FullChanges<MasterEvent<T>>> {
     inner: Option<T>,
+    /// Id of the row, kept after the row is deleted
+    id: Option<Id<T::IdentifiableType>>,
+    validator: Option<Validator<T>>,
+    violation: Option<Error>,
     marker: marker::PhantomData<C>,
 }
 
@@ -73,6 +82,9 @@ impl<T: GetId, C> Default for Master<T, C> {
     fn default() -> Self {
         Self {
             inner: None,
+            id: None,
+            validator: None,
+            violation: None,
             marker: marker::PhantomData,
         }
     }
@@ -81,10 +93,14 @@ impl<T: GetId, C> Default for Master<T, C> {
 impl<T, C> Clone for Master<T, C>
 where
     T: GetId + Clone,
+    Id<T::IdentifiableType>: Clone,
 {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            id: self.id.clone(),
+            validator: self.validator.clone(),
+            violation: self.violation.clone(),
             marker: marker::PhantomData,
         }
     }
@@ -115,19 +131,33 @@ where
     Id<<T as GetId>::IdentifiableType>: Clone,
 {
     pub fn new(row: T) -> (Self, C) {
-        let mut result = Self {
-            inner: None,
-            marker: marker::PhantomData,
-        };
+        let mut result = Self::default();
 
         let changes = result.create(row);
         (result, changes)
     }
 
+    /// Validates row on each creation and update, as well as the existing row
+    /// right away. Violations are reported by `check` until the row becomes
+    /// valid again (e.g. on rollback).
+    pub fn with_validator<F>(mut self, validator: F) -> Self
+    where
+        F: 'static + Fn(&T) -> Result<()>,
+    {
+        self.validator = Some(Rc::new(validator));
+        self.validate();
+        self
+    }
+
     pub fn try_get_id(&self) -> Option<Id<T::IdentifiableType>> {
         self.inner.as_ref().map(GetId::get_id)
     }
 
+    /// Id of the row even if it was deleted since, `None` if it was never created
+    pub fn last_id(&self) -> Option<Id<T::IdentifiableType>> {
+        self.id.clone()
+    }
+
     pub fn get(&self) -> &T {
         self.inner.as_ref().expect("not deleted")
     }
@@ -175,6 +205,33 @@ where
         }
     }
 
+    /// Like `update` but the modification can use ambient context,
+    /// e.g. to stamp `updated_at` from a clock, which also validates the row
+    pub fn update_in_context<TCtx, F>(
+        &mut self,
+        context: &mut TCtx,
+        f: F,
+    ) -> StdResult<C, NotFound<()>>
+    where
+        F: FnOnce(&mut TCtx, &mut T),
+        T: Eq + Clone,
+        C: NoopChange,
+        TCtx: ValidationContext<T>,
+    {
+        if let Some(existing) = &self.inner {
+            let mut modified = existing.clone();
+            f(context, &mut modified);
+
+            if existing == &modified {
+                Ok(C::noop())
+            } else {
+                Ok(self.applied_in_context(context, Updated(modified)))
+            }
+        } else {
+            Err(NotFound(()))
+        }
+    }
+
     pub fn delete(&mut self) -> StdResult<C, NotFound<()>> {
         if let Some(existing) = &self.inner {
             let id = existing.get_id();
@@ -185,6 +242,22 @@ where
     }
 }
 
+impl<T: GetId, C> Master<T, C> {
+    pub fn check(&self) -> Result<()> {
+        match &self.violation {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn validate(&mut self) {
+        self.violation = match (&self.validator, &self.inner) {
+            (Some(validator), Some(row)) => validator(row).err(),
+            _ => None,
+        };
+    }
+}
+
 impl<T, C> Historic for Master<T, C>
 where
     T: GetId,
@@ -199,10 +272,11 @@ where
     Id<T::IdentifiableType>: Clone,
 {
     fn apply(&mut self, event: Self::EventType) -> Self::EventType {
-        match event {
+        let undo = match event {
             Created(x) => {
                 let id = x.get_id();
                 self.inner = Some(x);
+                self.id = Some(id.clone());
                 Deleted(id)
             }
             Updated(x) => {
@@ -213,7 +287,26 @@ where
                 let old = self.inner.take();
                 Created(old.expect("Dev err: delete before create"))
             }
+        };
+        self.validate();
+        undo
+    }
+}
+
+impl<T, C, TCtx> ChangableInContext<TCtx> for Master<T, C>
+where
+    T: GetId,
+    Id<T::IdentifiableType>: Clone,
+    TCtx: ValidationContext<T>,
+{
+    fn apply_in_context(&mut self, context: &mut TCtx, event: Self::EventType)
+        -> Self::EventType
+    {
+        let undo = self.apply(event);
+        if let (None, Some(row)) = (&self.violation, &self.inner) {
+            self.violation = context.validate(row).err();
         }
+        undo
     }
 }
 
@@ -277,6 +370,50 @@ mod tests {
         );
     }
 
+    /// Clock which ticks on each reading
+    struct Clock(u32);
+
+    impl ValidationContext<MyEntity> for Clock {
+        fn validate(&mut self, entity: &MyEntity) -> Result<()> {
+            if entity.name.ends_with(&format!("@{}", self.0)) {
+                Ok(())
+            } else {
+                Err(Error::validation("stamped by another clock"))
+            }
+        }
+    }
+
+    #[test]
+    fn should_update_in_context() {
+        let mut sut = setup();
+        let mut clock = Clock(7);
+
+        let changes: FullChanges<_> = sut
+            .update_in_context(&mut clock, |clock, x| {
+                clock.0 += 1;
+                x.name = format!("bar@{}", clock.0);
+            })
+            .unwrap();
+
+        assert_eq!(sut.get().name.as_str(), "bar@8");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(sut.check(), Ok(()));
+    }
+
+    #[test]
+    fn should_validate_row_in_context() {
+        let mut sut = setup();
+
+        let _: FullChanges<_> = sut
+            .update_in_context(&mut Clock(7), |_, x| x.name = "bar@6".into())
+            .unwrap();
+
+        assert_eq!(
+            sut.check(),
+            Err(Error::validation("stamped by another clock"))
+        );
+    }
+
     #[test]
     fn should_update() {
         let mut sut = setup();
@@ -299,6 +436,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_report_invalid_row_until_fixed() {
+        let mut sut = Master::<MyEntity>::default().with_validator(|x| {
+            if x.name.is_empty() {
+                Err(Error::from_text("empty name".into()))
+            } else {
+                Ok(())
+            }
+        });
+        let _: FullChanges<_> = sut.create(MyEntity {
+            id: ID,
+            name: "foo".into(),
+        });
+        assert_eq!(sut.check(), Ok(()));
+
+        let changes: FullChanges<_> = sut.update(|x| x.name.clear()).unwrap();
+        assert_eq!(sut.check(), Err(Error::from_text("empty name".into())));
+
+        for c in changes {
+            sut.apply(c.take_undo());
+        }
+        assert_eq!(sut.check(), Ok(()));
+    }
+
+    #[test]
+    fn should_validate_existing_row_when_validator_attached() {
+        let sut = setup().with_validator(|x| {
+            if x.name.len() < 5 {
+                Err(Error::from_text("short name".into()))
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(sut.check(), Err(Error::from_text("short name".into())));
+    }
+
     #[test]
     fn should_delete() {
         let mut sut = setup();
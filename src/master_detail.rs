@@ -0,0 +1,593 @@
+use crate::changable::Changable;
+use crate::changes::{FullChange, FullChanges, Record};
+use crate::details::{Details, DetailsEvent, DetailsOwner};
+use crate::historic::Historic;
+use crate::identifiable::*;
+use crate::invariants::Invariants;
+use crate::master::{Master, MasterEvent};
+use crate::result::{Error, Result};
+use crate::streamable::{EventKind, KindOfEvent, Streamable};
+use crate::streaming::Stream;
+use crate::streaming_strategies::CloneRedoStreamingStrategy;
+use crate::undoable::Undoable;
+use std::any;
+use std::cmp::{Eq, PartialEq};
+use std::fmt;
+use std::hash;
+use std::result::Result as StdResult;
+
+pub enum MasterDetailEvent<M, D>
+where
+    M: GetId,
+    D: GetId,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+    Master(MasterEvent<M>),
+    Details(Id<M::IdentifiableType>, DetailsEvent<D>),
+}
+
+impl<M, D> KindOfEvent for MasterDetailEvent<M, D>
+where
+    M: GetId,
+    D: GetId,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+    fn kind_of_event(&self) -> EventKind {
+        match self {
+            MasterDetailEvent::Master(MasterEvent::Created(_)) => EventKind::Creation,
+            MasterDetailEvent::Master(MasterEvent::Deleted(_)) => EventKind::Deletion,
+            _ => EventKind::Other,
+        }
+    }
+}
+
+impl<M, D> fmt::Debug for MasterDetailEvent<M, D>
+where
+    M: GetId + fmt::Debug,
+    D: GetId + fmt::Debug,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: fmt::Debug,
+    Id<D::IdentifiableType>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MasterDetailEvent::Master(e) => write!(f, "MasterDetailEvent::Master({:?})", e),
+            MasterDetailEvent::Details(id, e) => {
+                write!(f, "MasterDetailEvent::Details({:?}, {:?})", id, e)
+            }
+        }
+    }
+}
+
+impl<M, D> Clone for MasterDetailEvent<M, D>
+where
+    M: GetId + Clone,
+    D: GetId + Clone,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+    Id<D::IdentifiableType>: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            MasterDetailEvent::Master(e) => MasterDetailEvent::Master(e.clone()),
+            MasterDetailEvent::Details(id, e) => MasterDetailEvent::Details(id.clone(), e.clone()),
+        }
+    }
+}
+
+impl<M, D> PartialEq for MasterDetailEvent<M, D>
+where
+    M: GetId + PartialEq,
+    D: GetId + PartialEq,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (MasterDetailEvent::Master(x), MasterDetailEvent::Master(y)) => x == y,
+            (MasterDetailEvent::Details(xid, x), MasterDetailEvent::Details(yid, y)) => {
+                xid == yid && x == y
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<M, D> Eq for MasterDetailEvent<M, D>
+where
+    M: GetId + Eq,
+    D: GetId + Eq,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+}
+
+/// Aggregate of a single master row and its details, e.g. order and its
+/// items. Equality compares state only, not the change history.
+pub struct MasterDetail<M, D>
+where
+    M: GetId,
+    D: GetId,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+    master: Master<M>,
+    details: Details<D>,
+    changes: Record<FullChange<MasterDetailEvent<M, D>>>,
+}
+
+impl<M, D> Default for MasterDetail<M, D>
+where
+    M: GetId,
+    D: GetId + Clone,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+    Id<D::IdentifiableType>: hash::Hash + Clone,
+{
+    fn default() -> Self {
+        Self {
+            master: Default::default(),
+            details: Default::default(),
+            changes: Default::default(),
+        }
+    }
+}
+
+impl<M, D> Clone for MasterDetail<M, D>
+where
+    M: GetId + Clone,
+    D: GetId + Clone,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+    Id<D::IdentifiableType>: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            master: self.master.clone(),
+            details: self.details.clone(),
+            changes: self.changes.clone(),
+        }
+    }
+}
+
+impl<M, D> PartialEq for MasterDetail<M, D>
+where
+    M: GetId + PartialEq,
+    D: GetId + PartialEq,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.master == other.master && self.details == other.details
+    }
+}
+
+impl<M, D> Eq for MasterDetail<M, D>
+where
+    M: GetId + Eq,
+    D: GetId + Eq,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+}
+
+impl<M, D> fmt::Debug for MasterDetail<M, D>
+where
+    M: GetId + fmt::Debug,
+    D: GetId + fmt::Debug,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: fmt::Debug,
+    Id<D::IdentifiableType>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MasterDetail")
+            .field("master", &self.master)
+            .field("details", &self.details)
+            .finish()
+    }
+}
+
+impl<M, D> MasterDetail<M, D>
+where
+    M: GetId + Clone,
+    D: GetId + Clone,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+    Id<D::IdentifiableType>: hash::Hash + Clone,
+{
+    pub fn new(master: M) -> Self {
+        let (master, changes): (_, FullChanges<_>) = Master::new(master);
+        Self {
+            master,
+            details: Default::default(),
+            changes: changes.bubble_up(MasterDetailEvent::Master).into(),
+        }
+    }
+
+    /// Validates master row on each change, see `Master::with_validator`.
+    /// Attach to `Default::default()` before `Unstreamable::load_into` so
+    /// that loaded aggregate keeps validating.
+    pub fn with_master_validator<F>(mut self, validator: F) -> Self
+    where
+        F: 'static + Fn(&M) -> Result<()>,
+    {
+        self.master = self.master.with_validator(validator);
+        self
+    }
+
+    /// Validates each detail on change, see `Details::with_validator`
+    pub fn with_details_validator<F>(mut self, validator: F) -> Self
+    where
+        F: 'static + Fn(&D) -> Result<()>,
+    {
+        self.details = self.details.with_validator(validator);
+        self
+    }
+
+    pub fn master(&self) -> &M {
+        self.master.get()
+    }
+
+    pub fn try_master(&self) -> Option<&M> {
+        self.master.try_get()
+    }
+
+    pub fn details(&self) -> &Details<D> {
+        &self.details
+    }
+
+    /// Atomically changes master row, e.g. `|m| m.update(...)`
+    pub fn mutate_master<F, E>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Master<M>) -> StdResult<FullChanges<MasterEvent<M>>, E>,
+        Error: From<E>,
+    {
+        let mut trx = self.begin_changes();
+        trx.mutate_inner(|subj| f(&mut subj.master), MasterDetailEvent::Master)?;
+        trx.commit()
+    }
+
+    /// Atomically changes details, e.g. `|d| d.add_new(...)`.
+    /// Fails with `NotFound` when there is no master row.
+    pub fn mutate_details<F, E>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Details<D>) -> StdResult<FullChanges<DetailsEvent<D>>, E>,
+        Error: From<E>,
+    {
+        let id = self
+            .master
+            .try_get_id()
+            .ok_or_else(|| Error::not_found(&any::type_name::<M>()))?;
+        let mut trx = self.begin_changes();
+        trx.mutate_inner(
+            |subj| f(&mut subj.details),
+            move |e| MasterDetailEvent::Details(id.clone(), e),
+        )?;
+        trx.commit()
+    }
+}
+
+impl<M, D> DetailsOwner<D> for MasterDetail<M, D>
+where
+    M: GetId + Clone,
+    D: GetId + Clone,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+    Id<D::IdentifiableType>: hash::Hash + Clone,
+{
+    fn detail_by_qualified_id(&self, id: &QualifiedId<D::IdentifiableType>) -> Option<&D> {
+        let owner = self.try_master()?.get_id();
+        self.details.by_qualified_id(&owner, id)
+    }
+}
+
+impl<M, D> Identifiable for MasterDetail<M, D>
+where
+    M: GetId + Clone,
+    D: GetId,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+{
+    type IdType = <M::IdentifiableType as Identifiable>::IdType;
+
+    /// Id of the master row, also after the row is deleted
+    fn id(&self) -> Id<Self> {
+        self.master
+            .last_id()
+            .expect("Dev error: master was never created")
+            .convert()
+    }
+}
+
+impl<M, D> Historic for MasterDetail<M, D>
+where
+    M: GetId,
+    D: GetId,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+    type EventType = MasterDetailEvent<M, D>;
+}
+
+impl<M, D> Changable for MasterDetail<M, D>
+where
+    M: GetId,
+    D: GetId,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+    Id<D::IdentifiableType>: hash::Hash + Clone,
+{
+    fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+        match event {
+            MasterDetailEvent::Master(e) => MasterDetailEvent::Master(self.master.apply(e)),
+            MasterDetailEvent::Details(id, e) => {
+                MasterDetailEvent::Details(id, self.details.apply(e))
+            }
+        }
+    }
+}
+
+impl<M, D> Invariants for MasterDetail<M, D>
+where
+    M: GetId,
+    D: GetId,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+{
+    fn check(&self) -> Result<()> {
+        self.master.check()?;
+        self.details.check()
+    }
+}
+
+impl<M, D> Undoable for MasterDetail<M, D>
+where
+    M: GetId,
+    D: GetId,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+    Id<D::IdentifiableType>: hash::Hash + Clone,
+{
+    fn changes_mut(&mut self) -> &mut Record<FullChange<Self::EventType>> {
+        &mut self.changes
+    }
+}
+
+impl<M, D> Streamable for MasterDetail<M, D>
+where
+    M: GetId + Clone,
+    D: GetId + Clone,
+    D::IdentifiableType: Owned<OwnerType = M::IdentifiableType>,
+    Id<M::IdentifiableType>: Clone,
+    Id<D::IdentifiableType>: hash::Hash + Clone,
+{
+    fn stream_to<S>(&mut self, stream: &mut S) -> StdResult<usize, S::Error>
+    where
+        S: Stream<Self::EventType>,
+    {
+        CloneRedoStreamingStrategy::new(self).stream_to(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result::{AlreadyExists, ErrorKind};
+    use crate::storage::InMemoryStorage;
+    use crate::streamable::Unstreamable;
+    use crate::streaming_strategies::UndoRedoStreamingStrategy;
+    use pretty_assertions::assert_eq;
+    use std::rc::Rc;
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    struct TestMaster {
+        id: i32,
+        name: &'static str,
+    }
+
+    impl Identifiable for TestMaster {
+        type IdType = i32;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.id)
+        }
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    struct TestDetail {
+        id: i32,
+    }
+
+    impl Identifiable for TestDetail {
+        type IdType = i32;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.id)
+        }
+    }
+
+    impl Owned for TestDetail {
+        type OwnerType = TestMaster;
+    }
+
+    type Sut = MasterDetail<TestMaster, Rc<TestDetail>>;
+
+    fn setup(id: i32) -> Sut {
+        let mut sut = Sut::new(TestMaster { id, name: "new" });
+        sut.mutate_details(|d| d.add_new(TestDetail { id: 1 }.into()))
+            .unwrap();
+        sut.mutate_details(|d| d.add_new(TestDetail { id: 2 }.into()))
+            .unwrap();
+        sut
+    }
+
+    #[test]
+    fn should_bubble_up_details_changes_with_master_id() {
+        let mut sut = setup(42);
+
+        let changes = sut.take_changes().unwrap();
+
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            changes[1],
+            MasterDetailEvent::Details(
+                Id::new(42),
+                DetailsEvent::Created(TestDetail { id: 1 }.into())
+            )
+        );
+    }
+
+    #[test]
+    fn should_mutate_master() {
+        let mut sut = setup(42);
+
+        sut.mutate_master(|m| m.update(|x| x.name = "renamed"))
+            .unwrap();
+
+        assert_eq!(sut.master().name, "renamed");
+    }
+
+    #[test]
+    fn should_rollback_failed_mutation() {
+        let mut sut = setup(42);
+        let history_len = sut.changes_mut().history_len();
+
+        let result = sut.mutate_details(|d| d.add_new(TestDetail { id: 1 }.into()));
+
+        assert_eq!(
+            result,
+            Err(AlreadyExists(Rc::new(TestDetail { id: 1 })).into())
+        );
+        assert_eq!(sut.changes_mut().history_len(), history_len);
+        assert_eq!(sut.details().len(), 2);
+    }
+
+    #[test]
+    fn should_not_mutate_details_without_master() {
+        let mut deleted = setup(42);
+        deleted.mutate_master(|m| m.delete()).unwrap();
+        let history_len = deleted.changes_mut().history_len();
+
+        let result = deleted.mutate_details(|d| d.add_new(TestDetail { id: 3 }.into()));
+        let empty = Sut::default().mutate_details(|d| d.add_new(TestDetail { id: 3 }.into()));
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(empty.unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(deleted.changes_mut().history_len(), history_len);
+        assert_eq!(deleted.details().len(), 2);
+    }
+
+    #[test]
+    fn should_save_after_master_is_deleted() {
+        let mut storage = InMemoryStorage::new();
+        let mut sut = setup(42);
+        storage.save(&mut sut).unwrap();
+
+        sut.mutate_master(|m| m.delete()).unwrap();
+        let count = storage.save(&mut sut);
+
+        assert_eq!(count, Ok(1));
+        assert_eq!(sut.get_id(), Id::new(42));
+    }
+
+    fn unnamed_rejected(x: &TestMaster) -> Result<()> {
+        if x.name.is_empty() {
+            Err(Error::validation("unnamed"))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_validate_initial_master_row() {
+        let sut = Sut::new(TestMaster { id: 42, name: "" }).with_master_validator(unnamed_rejected);
+
+        assert_eq!(sut.check(), Err(Error::validation("unnamed")));
+    }
+
+    #[test]
+    fn should_keep_validators_when_loaded_into_empty_aggregate() {
+        let mut storage = InMemoryStorage::new();
+        storage.save(&mut setup(42)).unwrap();
+        let empty = Sut::default()
+            .with_master_validator(unnamed_rejected)
+            .with_details_validator(|x| {
+                if x.id > 2 {
+                    Err(Error::validation("too many"))
+                } else {
+                    Ok(())
+                }
+            });
+
+        let mut loaded = storage.load_into(&Id::new(42), empty).unwrap();
+        let renamed = loaded.mutate_master(|m| m.update(|x| x.name = ""));
+        let added = loaded.mutate_details(|d| d.add_new(TestDetail { id: 3 }.into()));
+
+        assert_eq!(renamed, Err(Error::validation("unnamed")));
+        assert_eq!(added, Err(Error::validation("too many")));
+        assert_eq!(loaded, setup(42));
+    }
+
+    #[test]
+    fn should_load_streamed_changes() {
+        let mut sut = setup(42);
+        let id = Id::new(1);
+        sut.mutate_details(|d| d.remove_by_id(&id)).unwrap();
+
+        let loaded = Sut::load(sut.take_changes().unwrap()).unwrap();
+
+        assert_eq!(loaded, sut);
+        assert_eq!(loaded.id(), Id::new(42));
+    }
+
+    #[test]
+    fn should_stream_same_changes_with_undo_redo_strategy() {
+        let mut sut = setup(42);
+        let id = Id::new(1);
+        sut.mutate_details(|d| d.remove_by_id(&id)).unwrap();
+        let mut by_undo_redo = Vec::new();
+
+        UndoRedoStreamingStrategy::new(&mut sut)
+            .stream_to(&mut by_undo_redo)
+            .unwrap();
+
+        assert_eq!(by_undo_redo, sut.take_changes().unwrap());
+        assert_eq!(sut.details().len(), 1);
+    }
+
+    #[test]
+    fn should_find_detail_by_qualified_id() {
+        let sut = setup(42);
+
+        let found = sut.detail_by_qualified_id(&QualifiedId::new(Id::new(42), Id::new(1)));
+        let foreign = sut.detail_by_qualified_id(&QualifiedId::new(Id::new(7), Id::new(1)));
+
+        assert_eq!(found, Some(&TestDetail { id: 1 }.into()));
+        assert_eq!(foreign, None);
+    }
+
+    #[test]
+    fn should_load_detail_from_storage_by_qualified_id() {
+        let mut storage = InMemoryStorage::new();
+        storage.save(&mut setup(42)).unwrap();
+        storage.save(&mut setup(7)).unwrap();
+        let id = QualifiedId::new(Id::new(7), Id::new(2));
+
+        let loaded: Rc<TestDetail> = storage.load_detail(&id).unwrap();
+        let missing = storage.load_detail(&QualifiedId::new(Id::new(8), Id::new(2)));
+
+        assert_eq!(loaded, Rc::new(TestDetail { id: 2 }));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn should_load_many_and_omit_deleted() {
+        let mut first = setup(1);
+        let mut second = setup(2);
+        second.mutate_master(|m| m.delete()).unwrap();
+
+        let events = first
+            .take_changes()
+            .unwrap()
+            .into_iter()
+            .map(|e| (1, e))
+            .chain(second.take_changes().unwrap().into_iter().map(|e| (2, e)));
+
+        let loaded = Sut::load_many(events).unwrap();
+
+        assert_eq!(loaded, vec![first]);
+    }
+}
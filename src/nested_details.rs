@@ -0,0 +1,554 @@
+use crate::changable::Changable;
+use crate::changes::FullChanges;
+use crate::details::{Details, DetailsEvent, DetailsEvent::Updated};
+use crate::historic::Historic;
+use crate::identifiable::*;
+use crate::result::{AddError, NotFound, UniqueViolation, UpdateError};
+use std::cmp::{Eq, PartialEq};
+use std::fmt;
+use std::hash;
+use std::result::Result as StdResult;
+use std::slice;
+use NestedDetailsEvent::*;
+
+/// Event of a details collection whose items own details themselves.
+/// `Child` carries id of the item which the nested event belongs to.
+pub enum NestedDetailsEvent<T>
+where
+    T: GetId + Historic,
+    T::IdentifiableType: Owned,
+{
+    Own(DetailsEvent<T>),
+    Child(Id<T::IdentifiableType>, T::EventType),
+}
+
+impl<T> NestedDetailsEvent<T>
+where
+    Id<T::IdentifiableType>: Clone,
+    T: GetId + Historic,
+    T::IdentifiableType: Owned,
+{
+    pub fn get_id(&self) -> Option<Id<T::IdentifiableType>> {
+        match self {
+            Own(e) => e.get_id(),
+            Child(id, _) => Some(id.clone()),
+        }
+    }
+}
+
+impl<T> fmt::Debug for NestedDetailsEvent<T>
+where
+    T: fmt::Debug + GetId + Historic,
+    T::IdentifiableType: Owned,
+    T::EventType: fmt::Debug,
+    Id<T::IdentifiableType>: fmt::Debug,
+    Id<<T::IdentifiableType as Owned>::OwnerType>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Own(e) => write!(f, "NestedDetailsEvent::Own({:?})", e),
+            Child(id, e) => write!(f, "NestedDetailsEvent::Child({:?}, {:?})", id, e),
+        }
+    }
+}
+
+impl<T> PartialEq for NestedDetailsEvent<T>
+where
+    T: PartialEq + GetId + Historic,
+    T::IdentifiableType: Owned,
+    T::EventType: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Own(x), Own(y)) => x == y,
+            (Child(xid, x), Child(yid, y)) => xid == yid && x == y,
+            _ => false,
+        }
+    }
+}
+
+impl<T> Eq for NestedDetailsEvent<T>
+where
+    T: Eq + GetId + Historic,
+    T::IdentifiableType: Owned,
+    T::EventType: Eq,
+{
+}
+
+impl<T> Clone for NestedDetailsEvent<T>
+where
+    T: Clone + GetId + Historic,
+    T::IdentifiableType: Owned,
+    T::EventType: Clone,
+    Id<T::IdentifiableType>: Clone,
+    Id<<T::IdentifiableType as Owned>::OwnerType>: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Own(e) => Own(e.clone()),
+            Child(id, e) => Child(id.clone(), e.clone()),
+        }
+    }
+}
+
+/// Details collection which items are aggregates of their own details,
+/// e.g. order -> line -> allocations. Item changes are bubbled up as
+/// `NestedDetailsEvent::Child` tagged with the item id.
+pub struct NestedDetails<T>
+where
+    T: GetId + Historic,
+    T::IdentifiableType: Owned,
+{
+    items: Details<T>,
+}
+
+impl<T> Default for NestedDetails<T>
+where
+    T: GetId + Historic + Clone,
+    T::IdentifiableType: Owned,
+    T::EventType: Clone,
+    Id<T::IdentifiableType>: hash::Hash + Clone,
+    Id<<T::IdentifiableType as Owned>::OwnerType>: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps validators, indexes and unique keys configured on `items`
+impl<T> From<Details<T>> for NestedDetails<T>
+where
+    T: GetId + Historic,
+    T::IdentifiableType: Owned,
+{
+    fn from(items: Details<T>) -> Self {
+        Self { items }
+    }
+}
+
+impl<T> Clone for NestedDetails<T>
+where
+    T: GetId + Historic + Clone,
+    T::IdentifiableType: Owned,
+    DetailsEvent<T>: Clone,
+    Id<T::IdentifiableType>: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            items: self.items.clone(),
+        }
+    }
+}
+
+impl<T> Eq for NestedDetails<T>
+where
+    T: GetId + Historic + Eq,
+    T::IdentifiableType: Owned,
+{
+}
+
+impl<T> PartialEq for NestedDetails<T>
+where
+    T: GetId + Historic + PartialEq,
+    T::IdentifiableType: Owned,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.items.eq(&other.items)
+    }
+}
+
+impl<T> fmt::Debug for NestedDetails<T>
+where
+    T: GetId + Historic + fmt::Debug,
+    T::IdentifiableType: Owned,
+    Id<T::IdentifiableType>: fmt::Debug,
+    Id<<T::IdentifiableType as Owned>::OwnerType>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.items, f)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a NestedDetails<T>
+where
+    T: GetId + Historic,
+    T::IdentifiableType: Owned,
+{
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (&self.items).into_iter()
+    }
+}
+
+impl<T> Historic for NestedDetails<T>
+where
+    T: GetId + Historic,
+    T::IdentifiableType: Owned,
+{
+    type EventType = NestedDetailsEvent<T>;
+}
+
+impl<T> Changable for NestedDetails<T>
+where
+    T: GetId + Changable,
+    T::IdentifiableType: Owned,
+    Id<T::IdentifiableType>: hash::Hash + Clone,
+    Id<<T::IdentifiableType as Owned>::OwnerType>: Clone,
+{
+    fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+        match event {
+            Own(e) => Own(self.items.apply(e)),
+            Child(id, e) => {
+                let undo = self
+                    .items
+                    .change_in_place(&id, |item| item.apply(e))
+                    .expect("Dev error: id not found");
+                Child(id, undo)
+            }
+        }
+    }
+}
+
+impl<T> NestedDetails<T>
+where
+    T: GetId + Historic + Clone,
+    T::IdentifiableType: Owned,
+    T::EventType: Clone,
+    Id<T::IdentifiableType>: hash::Hash + Clone,
+    Id<<T::IdentifiableType as Owned>::OwnerType>: Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            items: Details::new(),
+        }
+    }
+
+    pub fn items(&self) -> &Details<T> {
+        &self.items
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    pub fn by_id(&self, id: &Id<T::IdentifiableType>) -> Option<&T> {
+        self.items.by_id(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn add_new(&mut self, item: T) -> StdResult<FullChanges<NestedDetailsEvent<T>>, AddError<T>>
+    where
+        T: Eq,
+    {
+        let changes = self.items.add_new(item)?;
+        Ok(changes.bubble_up(Own))
+    }
+
+    pub fn update(
+        &mut self,
+        item: T,
+    ) -> StdResult<FullChanges<NestedDetailsEvent<T>>, UpdateError<T>>
+    where
+        T: Eq,
+    {
+        let changes = self.items.update(item)?;
+        Ok(changes.bubble_up(Own))
+    }
+
+    pub fn update_or_add(
+        &mut self,
+        item: T,
+    ) -> StdResult<FullChanges<NestedDetailsEvent<T>>, UniqueViolation<T>>
+    where
+        T: Eq + fmt::Debug,
+    {
+        Ok(self.items.update_or_add(item)?.bubble_up(Own))
+    }
+
+    pub fn set_all(
+        &mut self,
+        items: impl IntoIterator<Item = T>,
+    ) -> StdResult<FullChanges<NestedDetailsEvent<T>>, UniqueViolation<T>>
+    where
+        T: Eq + fmt::Debug,
+    {
+        Ok(self.items.set_all(items)?.bubble_up(Own))
+    }
+
+    pub fn remove_by_id<'a>(
+        &mut self,
+        id: &'a Id<T::IdentifiableType>,
+    ) -> StdResult<FullChanges<NestedDetailsEvent<T>>, NotFound<&'a Id<T::IdentifiableType>>> {
+        let changes = self.items.remove_by_id(id)?;
+        Ok(changes.bubble_up(Own))
+    }
+
+    /// Changes item's own details on a copy which then replaces the item,
+    /// so that validators, indexes and unique keys of `items` see the change.
+    /// Resulting changes are bubbled up with the item id attached so that
+    /// `apply` can route them back.
+    pub fn mutate_child<F, E>(
+        &mut self,
+        id: &Id<T::IdentifiableType>,
+        f: F,
+    ) -> StdResult<FullChanges<NestedDetailsEvent<T>>, E>
+    where
+        F: FnOnce(&mut T) -> StdResult<FullChanges<T::EventType>, E>,
+        E: From<NotFound<Id<T::IdentifiableType>>> + From<UniqueViolation<T>>,
+    {
+        let mut item = self
+            .items
+            .by_id(id)
+            .cloned()
+            .ok_or_else(|| NotFound(id.clone()))?;
+        let changes = f(&mut item)?;
+        assert!(&item.get_id() == id, "Dev error: child id must not change");
+        let item = self.items.check_unique(item)?;
+        self.items.apply(Updated(item));
+        let id = id.clone();
+        Ok(changes.bubble_up(move |e| Child(id.clone(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changes::FullChange;
+    use crate::query::Indexed;
+    use crate::result::{Error, ErrorKind, Result};
+    use crate::streamable::Unstreamable;
+    use pretty_assertions::assert_eq;
+
+    struct TestOrder;
+
+    impl Identifiable for TestOrder {
+        type IdType = i32;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(0)
+        }
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    struct TestLine {
+        id: i32,
+        allocations: Details<TestAllocation>,
+    }
+
+    impl Identifiable for TestLine {
+        type IdType = i32;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.id)
+        }
+    }
+
+    impl Owned for TestLine {
+        type OwnerType = TestOrder;
+    }
+
+    impl Historic for TestLine {
+        type EventType = DetailsEvent<TestAllocation>;
+    }
+
+    impl Changable for TestLine {
+        fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+            self.allocations.apply(event)
+        }
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    struct TestAllocation {
+        id: i32,
+        quantity: u32,
+    }
+
+    impl Identifiable for TestAllocation {
+        type IdType = i32;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.id)
+        }
+    }
+
+    impl Owned for TestAllocation {
+        type OwnerType = TestLine;
+    }
+
+    /// Lines by total allocated quantity
+    struct ByTotal;
+
+    impl Indexed<TestLine> for ByTotal {
+        type Key = u32;
+
+        fn key(item: &TestLine) -> u32 {
+            item.allocations.iter().map(|a| a.quantity).sum()
+        }
+    }
+
+    fn line(id: i32) -> TestLine {
+        TestLine {
+            id,
+            allocations: Details::new(),
+        }
+    }
+
+    fn allocation(id: i32, quantity: u32) -> TestAllocation {
+        TestAllocation { id, quantity }
+    }
+
+    fn try_allocate(
+        sut: &mut NestedDetails<TestLine>,
+        line_id: i32,
+        a: TestAllocation,
+    ) -> Result<FullChanges<NestedDetailsEvent<TestLine>>> {
+        sut.mutate_child(&Id::new(line_id), |line| Ok(line.allocations.add_new(a)?))
+    }
+
+    fn allocate(
+        sut: &mut NestedDetails<TestLine>,
+        line_id: i32,
+        a: TestAllocation,
+    ) -> FullChanges<NestedDetailsEvent<TestLine>> {
+        try_allocate(sut, line_id, a).unwrap()
+    }
+
+    fn setup() -> (NestedDetails<TestLine>, Vec<NestedDetailsEvent<TestLine>>) {
+        let mut sut = NestedDetails::new();
+        let mut history = Vec::new();
+        history.extend(sut.add_new(line(1)).unwrap());
+        history.extend(sut.add_new(line(2)).unwrap());
+        history.extend(allocate(&mut sut, 2, allocation(10, 5)));
+        history.extend(allocate(&mut sut, 1, allocation(11, 3)));
+
+        let redos = history
+            .into_iter()
+            .filter_map(FullChange::take_redo)
+            .collect();
+        (sut, redos)
+    }
+
+    #[test]
+    fn child_changes_are_tagged_with_owner_id() {
+        let mut sut = NestedDetails::new();
+        sut.add_new(line(1)).unwrap();
+
+        let changes: Vec<_> = allocate(&mut sut, 1, allocation(10, 5)).into();
+
+        assert_eq!(
+            changes,
+            vec![FullChange::new(
+                Child(Id::new(1), DetailsEvent::Created(allocation(10, 5))),
+                Child(Id::new(1), DetailsEvent::Deleted(Id::new(10)))
+            )]
+        );
+    }
+
+    #[test]
+    fn should_fail_to_mutate_missing_child() {
+        let mut sut = NestedDetails::<TestLine>::new();
+
+        let result = try_allocate(&mut sut, 1, allocation(10, 5));
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn should_validate_child_after_mutation() {
+        let mut sut = NestedDetails::from(Details::new().with_validator(|x: &TestLine| {
+            let total: u32 = x.allocations.iter().map(|a| a.quantity).sum();
+            if total > 5 {
+                Err(Error::validation(format!(
+                    "line {} is over allocated",
+                    x.id
+                )))
+            } else {
+                Ok(())
+            }
+        }));
+        sut.add_new(line(1)).unwrap();
+        allocate(&mut sut, 1, allocation(10, 5));
+        assert_eq!(sut.items().check(), Ok(()));
+
+        let changes = allocate(&mut sut, 1, allocation(11, 1));
+        assert!(sut.items().check().is_err());
+
+        for c in changes {
+            sut.apply(c.take_undo());
+        }
+        assert_eq!(sut.items().check(), Ok(()));
+    }
+
+    #[test]
+    fn should_reindex_child_changed_in_place() {
+        let mut sut = NestedDetails::from(Details::new().with_index::<ByTotal>());
+        sut.add_new(line(1)).unwrap();
+        sut.add_new(line(2)).unwrap();
+
+        sut.apply(Child(Id::new(2), DetailsEvent::Created(allocation(10, 5))));
+
+        let ids = |total| -> Vec<_> {
+            sut.items()
+                .by_index::<ByTotal>(&total)
+                .into_iter()
+                .map(|x| x.id)
+                .collect()
+        };
+        assert_eq!(ids(0), vec![1]);
+        assert_eq!(ids(5), vec![2]);
+    }
+
+    #[test]
+    fn should_reject_child_mutation_breaking_unique_key() {
+        let mut sut =
+            NestedDetails::from(Details::new().with_unique_key("total", |x: &TestLine| {
+                x.allocations.iter().map(|a| a.quantity).sum::<u32>()
+            }));
+        sut.add_new(line(1)).unwrap();
+        allocate(&mut sut, 1, allocation(10, 5));
+        sut.add_new(line(2)).unwrap();
+
+        let result = try_allocate(&mut sut, 2, allocation(11, 5));
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Validation);
+        assert_eq!(sut.by_id(&Id::new(2)), Some(&line(2)));
+    }
+
+    #[test]
+    fn should_undo_child_changes() {
+        let mut sut = NestedDetails::new();
+        sut.add_new(line(1)).unwrap();
+
+        for c in allocate(&mut sut, 1, allocation(10, 5)) {
+            sut.apply(c.take_undo());
+        }
+
+        assert_eq!(sut.by_id(&Id::new(1)), Some(&line(1)));
+    }
+
+    #[test]
+    fn should_load_nested_events_into_right_parent() {
+        let (expected, events) = setup();
+
+        let loaded = NestedDetails::<TestLine>::load(events).unwrap();
+
+        assert_eq!(loaded, expected);
+        assert_eq!(
+            loaded
+                .by_id(&Id::new(2))
+                .unwrap()
+                .allocations
+                .by_id(&Id::new(10)),
+            Some(&allocation(10, 5))
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// Secondary index over items of type `T`, declared on a marker type.
+/// Registered via `Details::with_index` and maintained on `apply`.
+pub trait Indexed<T>: 'static {
+    type Key: Eq + Hash + Clone;
+
+    fn key(item: &T) -> Self::Key;
+}
+
+/// Ordered selection of borrowed items
+pub struct Query<'a, T> {
+    items: Vec<&'a T>,
+}
+
+impl<'a, T> Query<'a, T> {
+    pub fn new(items: impl IntoIterator<Item = &'a T>) -> Self {
+        Self {
+            items: items.into_iter().collect(),
+        }
+    }
+
+    pub fn where_<P>(mut self, mut predicate: P) -> Self
+    where
+        P: FnMut(&T) -> bool,
+    {
+        self.items.retain(|x| predicate(x));
+        self
+    }
+
+    /// Stable ascending sort, so successive calls order by the last key first
+    pub fn order_by<K, F>(mut self, mut key: F) -> Self
+    where
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        self.items.sort_by_key(|x| key(x));
+        self
+    }
+
+    pub fn order_by_desc<K, F>(mut self, mut key: F) -> Self
+    where
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        self.items.sort_by_key(|x| std::cmp::Reverse(key(x)));
+        self
+    }
+
+    pub fn skip(mut self, count: usize) -> Self {
+        let count = count.min(self.items.len());
+        self.items.drain(..count);
+        self
+    }
+
+    pub fn take(mut self, count: usize) -> Self {
+        self.items.truncate(count);
+        self
+    }
+
+    /// Groups in order of the first item of each group
+    pub fn group_by<K, F>(self, mut key: F) -> Vec<(K, Vec<&'a T>)>
+    where
+        K: Eq + Hash + Clone,
+        F: FnMut(&T) -> K,
+    {
+        let mut positions = HashMap::new();
+        let mut groups: Vec<(K, Vec<&'a T>)> = Vec::new();
+        for x in self.items {
+            let k = key(x);
+            let pos = *positions.entry(k.clone()).or_insert_with(|| {
+                groups.push((k, Vec::new()));
+                groups.len() - 1
+            });
+            groups[pos].1.push(x);
+        }
+        groups
+    }
+
+    pub fn first(&self) -> Option<&'a T> {
+        self.items.first().copied()
+    }
+
+    pub fn count(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn to_vec(self) -> Vec<&'a T> {
+        self.items
+    }
+}
+
+impl<'a, T> IntoIterator for Query<'a, T> {
+    type Item = &'a T;
+    type IntoIter = std::vec::IntoIter<&'a T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+/// Collection which can be queried, e.g. result of `InMemoryStorage::load_all`
+pub trait Queryable {
+    type Item;
+
+    fn query(&self) -> Query<'_, Self::Item>;
+}
+
+impl<T> Queryable for [T] {
+    type Item = T;
+
+    fn query(&self) -> Query<'_, T> {
+        Query::new(self)
+    }
+}
+
+impl<T> Queryable for Vec<T> {
+    type Item = T;
+
+    fn query(&self) -> Query<'_, T> {
+        Query::new(self)
+    }
+}
+
+/// Type-erased index which tracks item positions in a collection
+pub(crate) trait IndexOps<T> {
+    fn inserted(&mut self, pos: usize, item: &T);
+
+    fn replaced(&mut self, pos: usize, old: &T, new: &T);
+
+    fn removed(&mut self, pos: usize, item: &T);
+
+    /// Item at `pos` is about to change in place
+    fn detached(&mut self, pos: usize, item: &T);
+
+    /// Item at `pos` was changed in place
+    fn attached(&mut self, pos: usize, item: &T);
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn clone_box(&self) -> Box<dyn IndexOps<T>>;
+}
+
+pub(crate) struct SecondaryIndex<I: Indexed<T>, T> {
+    positions: HashMap<I::Key, Vec<usize>>,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<I: Indexed<T>, T> SecondaryIndex<I, T> {
+    pub fn build<'a>(items: impl IntoIterator<Item = &'a T>) -> Self
+    where
+        T: 'a,
+    {
+        let mut result = Self {
+            positions: HashMap::new(),
+            marker: PhantomData,
+        };
+        for (pos, x) in items.into_iter().enumerate() {
+            result.insert(pos, x);
+        }
+        result
+    }
+
+    /// Positions of matching items in ascending order
+    pub fn positions(&self, key: &I::Key) -> &[usize] {
+        self.positions.get(key).map_or(&[], Vec::as_slice)
+    }
+}
+
+impl<I: Indexed<T>, T: 'static> IndexOps<T> for SecondaryIndex<I, T> {
+    fn inserted(&mut self, pos: usize, item: &T) {
+        self.insert(pos, item);
+    }
+
+    fn replaced(&mut self, pos: usize, old: &T, new: &T) {
+        self.forget(pos, old);
+        self.insert(pos, new);
+    }
+
+    /// Shifts every position after `pos`, so it costs O(n) like the
+    /// removal from the collection itself
+    fn removed(&mut self, pos: usize, item: &T) {
+        self.forget(pos, item);
+        for x in self.positions.values_mut().flatten() {
+            if *x > pos {
+                *x -= 1;
+            }
+        }
+    }
+
+    fn detached(&mut self, pos: usize, item: &T) {
+        self.forget(pos, item);
+    }
+
+    fn attached(&mut self, pos: usize, item: &T) {
+        self.insert(pos, item);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn IndexOps<T>> {
+        Box::new(Self {
+            positions: self.positions.clone(),
+            marker: PhantomData,
+        })
+    }
+}
+
+impl<I: Indexed<T>, T> SecondaryIndex<I, T> {
+    fn insert(&mut self, pos: usize, item: &T) {
+        let bucket = self.positions.entry(I::key(item)).or_default();
+        if let Err(at) = bucket.binary_search(&pos) {
+            bucket.insert(at, pos);
+        }
+    }
+
+    fn forget(&mut self, pos: usize, item: &T) {
+        let key = I::key(item);
+        if let Some(bucket) = self.positions.get_mut(&key) {
+            bucket.retain(|x| *x != pos);
+            if bucket.is_empty() {
+                self.positions.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[derive(Debug, PartialEq)]
+    struct Person {
+        name: &'static str,
+        city: &'static str,
+        age: u32,
+    }
+
+    fn people() -> Vec<Person> {
+        vec![
+            Person {
+                name: "ann",
+                city: "Kyiv",
+                age: 30,
+            },
+            Person {
+                name: "bob",
+                city: "Lviv",
+                age: 25,
+            },
+            Person {
+                name: "cid",
+                city: "Kyiv",
+                age: 41,
+            },
+            Person {
+                name: "dan",
+                city: "Odesa",
+                age: 19,
+            },
+        ]
+    }
+
+    fn names<'a>(items: impl IntoIterator<Item = &'a Person>) -> Vec<&'static str> {
+        items.into_iter().map(|x| x.name).collect()
+    }
+
+    #[test]
+    fn should_filter_order_and_paginate() {
+        let all = people();
+
+        let page = all
+            .query()
+            .where_(|x| x.age > 20)
+            .order_by_desc(|x| x.age)
+            .skip(1)
+            .take(1);
+
+        assert_eq!(names(page), vec!["ann"]);
+    }
+
+    #[test]
+    fn should_tolerate_skipping_past_the_end() {
+        let all = people();
+
+        assert_eq!(all.query().skip(10).count(), 0);
+    }
+
+    #[test]
+    fn should_group_in_order_of_appearance() {
+        let all = people();
+
+        let groups: Vec<_> = all
+            .query()
+            .order_by(|x| x.age)
+            .group_by(|x| x.city)
+            .into_iter()
+            .map(|(city, xs)| (city, names(xs)))
+            .collect();
+
+        assert_eq!(
+            groups,
+            vec![
+                ("Odesa", vec!["dan"]),
+                ("Lviv", vec!["bob"]),
+                ("Kyiv", vec!["ann", "cid"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,355 @@
+use crate::identifiable::{GetId, Id, Identifiable};
+use crate::joins::{ManyReferences, SingleReference};
+use crate::result::{DanglingReferences, RestrictedDeletion, Result};
+use crate::storage::InMemoryStorage;
+use crate::streamable::{KindOfEvent, Streamable, Unstreamable};
+use std::fmt;
+use std::hash::Hash;
+use std::result::Result as StdResult;
+
+/// Store which can tell whether an entity exists and is not deleted
+pub trait Existence<T: Identifiable> {
+    fn exists(&self, id: &Id<T>) -> bool;
+}
+
+/// What happens to referrers `R` when referenced `T` is deleted
+pub enum OnDelete<R, T: Identifiable> {
+    /// Deletion fails while any referrer exists
+    Restrict,
+    /// Referrer is deleted by the function
+    Cascade(fn(&mut R) -> Result<()>),
+    /// Reference to the given id is cleared by the function
+    Nullify(fn(&mut R, &Id<T>) -> Result<()>),
+}
+
+/// Integrity rule for references from aggregates `R` to aggregates `T`
+pub struct Reference<R, T: Identifiable> {
+    targets: fn(&R) -> Vec<Id<T>>,
+    on_delete: OnDelete<R, T>,
+}
+
+impl<R, T: Identifiable> Reference<R, T> {
+    pub fn single(on_delete: OnDelete<R, T>) -> Self
+    where
+        R: SingleReference<T>,
+    {
+        Self {
+            targets: |r| vec![r.reference()],
+            on_delete,
+        }
+    }
+
+    pub fn many(on_delete: OnDelete<R, T>) -> Self
+    where
+        R: ManyReferences<T>,
+    {
+        Self {
+            targets: |r| r.references().collect(),
+            on_delete,
+        }
+    }
+
+    pub fn references(&self, referrer: &R, target: &Id<T>) -> bool {
+        (self.targets)(referrer).contains(target)
+    }
+
+    /// Lists referenced ids which do not exist in `targets`
+    pub fn validate<S>(&self, referrer: &R, targets: &S) -> StdResult<(), DanglingReferences<Id<T>>>
+    where
+        S: Existence<T>,
+    {
+        let dangling: Vec<_> = (self.targets)(referrer)
+            .into_iter()
+            .filter(|id| !targets.exists(id))
+            .collect();
+        if dangling.is_empty() {
+            Ok(())
+        } else {
+            Err(DanglingReferences(dangling))
+        }
+    }
+
+    /// Saves referrer only if all its references exist
+    pub fn save_checked<S, TEvent, D>(
+        &self,
+        root: &mut R,
+        referrers: &mut InMemoryStorage<R, TEvent, D>,
+        targets: &S,
+    ) -> Result<usize>
+    where
+        S: Existence<T>,
+        R: Streamable<EventType = TEvent> + GetId,
+        R: Unstreamable<EventType = TEvent>,
+        Id<R::IdentifiableType>: Clone,
+        Id<T>: fmt::Debug,
+    {
+        self.validate(root, targets)?;
+        referrers.save(root)
+    }
+
+    /// Applies the policy to referrers of `target` which is about to be
+    /// deleted and returns count of changed referrers. `Restrict` fails
+    /// before any change if at least one referrer exists. `Cascade` and
+    /// `Nullify` change every referrer before saving, so referrers are
+    /// saved all together or not at all.
+    pub fn on_delete<TEvent, D>(
+        &self,
+        target: &Id<T>,
+        referrers: &mut InMemoryStorage<R, TEvent, D>,
+    ) -> Result<usize>
+    where
+        R: Streamable<EventType = TEvent> + GetId,
+        R: Unstreamable<EventType = TEvent>,
+        TEvent: Clone + KindOfEvent,
+        Id<R::IdentifiableType>: Clone + Hash + fmt::Debug,
+    {
+        let mut affected: Vec<_> = referrers
+            .load_all()?
+            .into_iter()
+            .filter(|r| self.references(r, target))
+            .collect();
+        let count = affected.len();
+
+        match self.on_delete {
+            OnDelete::Restrict if count > 0 => {
+                let ids = affected.iter().map(GetId::get_id).collect();
+                return Err(RestrictedDeletion(ids).into());
+            }
+            OnDelete::Restrict => {}
+            OnDelete::Cascade(delete) => {
+                for r in affected.iter_mut() {
+                    delete(r)?;
+                }
+                referrers.save_all(&mut affected)?;
+            }
+            OnDelete::Nullify(clear) => {
+                for r in affected.iter_mut() {
+                    clear(r, target)?;
+                }
+                referrers.save_all(&mut affected)?;
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changable::Changable;
+    use crate::changes::{FullChange, FullChanges, Record};
+    use crate::historic::Historic;
+    use crate::invariants::Invariants;
+    use crate::result::Error;
+    use crate::streamable::EventKind;
+    use crate::streaming::Stream;
+    use crate::streaming_strategies::CloneRedoStreamingStrategy;
+    use crate::test_utils::{TestAggregate, TestEvent};
+    use crate::undoable::Undoable;
+    use pretty_assertions::assert_eq;
+    use std::mem;
+    use std::vec;
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    enum LinkEvent {
+        Created(i32, Vec<i32>),
+        Deleted(i32, Vec<i32>),
+        Retargeted(Vec<i32>),
+    }
+
+    /// Aggregate referencing many `TestAggregate`s
+    #[derive(Debug, Default)]
+    struct Link {
+        id: i32,
+        targets: Vec<i32>,
+        changes: Record<FullChange<LinkEvent>>,
+    }
+
+    impl Link {
+        fn new(id: i32, targets: Vec<i32>) -> Self {
+            let mut result = Self::default();
+            let changes: FullChanges<_> = result.applied(LinkEvent::Created(id, targets));
+            result.changes.extend(changes);
+            result
+        }
+
+        fn change(&mut self, event: LinkEvent) -> Result<()> {
+            let mut trx = self.begin_changes();
+            trx.mutate(|subj| Ok::<_, Error>(subj.applied(event)))?;
+            trx.commit()
+        }
+
+        fn delete(&mut self) -> Result<()> {
+            self.change(LinkEvent::Deleted(self.id, self.targets.clone()))
+        }
+
+        fn unlink(&mut self, target: &Id<TestAggregate>) -> Result<()> {
+            let mut targets = self.targets.clone();
+            targets.retain(|x| x != target.raw());
+            self.change(LinkEvent::Retargeted(targets))
+        }
+    }
+
+    impl Identifiable for Link {
+        type IdType = i32;
+
+        fn id(&self) -> Id<Self> {
+            Id::new(self.id)
+        }
+    }
+
+    impl ManyReferences<TestAggregate> for Link {
+        type Iter = vec::IntoIter<Id<TestAggregate>>;
+
+        fn references(&self) -> Self::Iter {
+            let ids: Vec<_> = self.targets.iter().copied().map(Id::new).collect();
+            ids.into_iter()
+        }
+    }
+
+    impl Historic for Link {
+        type EventType = LinkEvent;
+    }
+
+    impl KindOfEvent for LinkEvent {
+        fn kind_of_event(&self) -> EventKind {
+            match self {
+                LinkEvent::Created(..) => EventKind::Creation,
+                LinkEvent::Deleted(..) => EventKind::Deletion,
+                LinkEvent::Retargeted(_) => EventKind::Other,
+            }
+        }
+    }
+
+    impl Changable for Link {
+        fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+            match event {
+                LinkEvent::Created(id, targets) => {
+                    self.id = id;
+                    self.targets = targets.clone();
+                    LinkEvent::Deleted(id, targets)
+                }
+                LinkEvent::Deleted(id, targets) => LinkEvent::Created(id, targets),
+                LinkEvent::Retargeted(targets) => {
+                    LinkEvent::Retargeted(mem::replace(&mut self.targets, targets))
+                }
+            }
+        }
+    }
+
+    impl Invariants for Link {}
+
+    impl Undoable for Link {
+        fn changes_mut(&mut self) -> &mut Record<FullChange<Self::EventType>> {
+            &mut self.changes
+        }
+    }
+
+    impl Streamable for Link {
+        fn stream_to<S>(&mut self, stream: &mut S) -> StdResult<usize, S::Error>
+        where
+            S: Stream<Self::EventType>,
+        {
+            CloneRedoStreamingStrategy::new(self).stream_to(stream)
+        }
+    }
+
+    type Targets = InMemoryStorage<TestAggregate, TestEvent>;
+    type Links = InMemoryStorage<Link, LinkEvent>;
+
+    fn setup(
+        on_delete: OnDelete<Link, TestAggregate>,
+    ) -> (Reference<Link, TestAggregate>, Targets, Links) {
+        let sut = Reference::many(on_delete);
+        let mut targets = Targets::new();
+        targets.save(&mut TestAggregate::new(1)).unwrap();
+        targets.save(&mut TestAggregate::new(2)).unwrap();
+        let mut links = Links::new();
+        sut.save_checked(&mut Link::new(10, vec![1, 2]), &mut links, &targets)
+            .unwrap();
+        sut.save_checked(&mut Link::new(20, vec![2]), &mut links, &targets)
+            .unwrap();
+        (sut, targets, links)
+    }
+
+    fn targets_of_links(links: &mut Links) -> Vec<(i32, Vec<i32>)> {
+        let mut result: Vec<_> = links
+            .load_all()
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.id, x.targets))
+            .collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn should_report_dangling_references_on_save() {
+        let (sut, mut targets, mut links) = setup(OnDelete::Restrict);
+        let mut deleted = targets.load(&Id::new(2)).unwrap();
+        deleted.delete().unwrap();
+        targets.save(&mut deleted).unwrap();
+
+        let result = sut.validate(&Link::new(30, vec![1, 2, 3]), &targets);
+        let saved = sut.save_checked(&mut Link::new(30, vec![3]), &mut links, &targets);
+
+        assert_eq!(result.map_err(|e| e.0), Err(vec![Id::new(2), Id::new(3)]));
+        assert!(saved.is_err());
+        assert_eq!(targets_of_links(&mut links).len(), 2);
+    }
+
+    #[test]
+    fn should_restrict_deletion_of_referenced() {
+        let (sut, _, mut links) = setup(OnDelete::Restrict);
+
+        let result = sut.on_delete(&Id::new(1), &mut links);
+
+        assert_eq!(
+            result,
+            Err(RestrictedDeletion(vec![Id::<Link>::new(10)]).into())
+        );
+        assert_eq!(sut.on_delete(&Id::new(3), &mut links), Ok(0));
+    }
+
+    #[test]
+    fn should_cascade_deletion() {
+        let (sut, _, mut links) = setup(OnDelete::Cascade(Link::delete));
+
+        let count = sut.on_delete(&Id::new(1), &mut links);
+
+        assert_eq!(count, Ok(1));
+        assert_eq!(targets_of_links(&mut links), vec![(20, vec![2])]);
+    }
+
+    #[test]
+    fn should_nullify_references() {
+        let (sut, _, mut links) = setup(OnDelete::Nullify(Link::unlink));
+
+        let count = sut.on_delete(&Id::new(2), &mut links);
+
+        assert_eq!(count, Ok(2));
+        assert_eq!(
+            targets_of_links(&mut links),
+            vec![(10, vec![1]), (20, vec![])]
+        );
+    }
+
+    #[test]
+    fn should_not_save_any_referrer_when_policy_fails_for_one() {
+        let (sut, _, mut links) = setup(OnDelete::Nullify(|r, target| {
+            if r.id == 20 {
+                Err(Error::validation("Link 20 must keep its target"))
+            } else {
+                r.unlink(target)
+            }
+        }));
+
+        let result = sut.on_delete(&Id::new(2), &mut links);
+
+        assert!(result.is_err());
+        assert_eq!(
+            targets_of_links(&mut links),
+            vec![(10, vec![1, 2]), (20, vec![2])]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use crate::streaming::Stream;
+use std::cmp;
+use std::iter;
+use std::result::Result as StdResult;
+use std::thread;
+use std::time::Duration;
+
+/// Log of entries which are written together with aggregate events
+/// and have to be delivered at least once.
+pub trait Outbox {
+    type Entry;
+
+    /// Entries not yet delivered, in order of writing, with their positions
+    fn undelivered(&self) -> Vec<(usize, Self::Entry)>;
+
+    /// Tells whether entry at `position` exists; unknown positions are ignored
+    fn mark_delivered(&mut self, position: usize) -> bool;
+}
+
+/// Delay before the retry `n` is `initial * 2^n` but not longer than `max`.
+/// `attempts` counts the first try too, so both 0 and 1 mean a single try.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Backoff {
+    pub attempts: usize,
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    pub fn delay(&self, retry: usize) -> Duration {
+        let factor = 1u32.checked_shl(retry as u32).unwrap_or(u32::MAX);
+        cmp::min(self.initial.saturating_mul(factor), self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            initial: Duration::from_millis(10),
+            max: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Hands undelivered outbox entries to a sink one by one. Entry is marked
+/// as delivered only after the sink accepted it, so a crash in between
+/// leads to redelivery rather than loss.
+pub struct Relay<S> {
+    sink: S,
+    backoff: Backoff,
+    sleep: fn(Duration),
+}
+
+impl<S> Relay<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            backoff: Backoff::default(),
+            sleep: thread::sleep,
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Replaces `thread::sleep` used between retries
+    pub fn with_sleep(mut self, sleep: fn(Duration)) -> Self {
+        self.sleep = sleep;
+        self
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Delivers all pending entries and returns their count.
+    /// Stops on the first entry which fails after all retries; it and
+    /// the following entries stay undelivered.
+    pub fn relay<O>(&mut self, outbox: &mut O) -> StdResult<usize, S::Error>
+    where
+        O: Outbox,
+        O::Entry: Clone,
+        S: Stream<O::Entry>,
+    {
+        let mut count = 0;
+        for (position, entry) in outbox.undelivered() {
+            self.deliver(entry)?;
+            outbox.mark_delivered(position);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn deliver<E>(&mut self, entry: E) -> StdResult<(), S::Error>
+    where
+        E: Clone,
+        S: Stream<E>,
+    {
+        let mut retry = 0;
+        loop {
+            match self.sink.stream(iter::once(entry.clone())) {
+                Ok(_) => return Ok(()),
+                Err(e) if retry + 1 >= self.backoff.attempts => return Err(e),
+                Err(_) => {
+                    (self.sleep)(self.backoff.delay(retry));
+                    retry += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identifiable::Id;
+    use crate::storage::InMemoryStorage;
+    use crate::test_utils::{TestAggregate, TestDomainEvent, TestEvent};
+    use pretty_assertions::assert_eq;
+    use TestDomainEvent::Renamed;
+
+    type Entry = (Id<TestAggregate>, TestDomainEvent);
+
+    struct FlakySink {
+        failures_left: usize,
+        attempts: usize,
+        received: Vec<Entry>,
+    }
+
+    impl FlakySink {
+        fn failing(times: usize) -> Self {
+            Self {
+                failures_left: times,
+                attempts: 0,
+                received: Vec::new(),
+            }
+        }
+    }
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct SinkDown;
+
+    impl Stream<Entry> for FlakySink {
+        type Error = SinkDown;
+
+        fn stream<I>(&mut self, events: I) -> StdResult<usize, Self::Error>
+        where
+            I: IntoIterator<Item = Entry>,
+        {
+            self.attempts += 1;
+            if self.failures_left > 0 {
+                self.failures_left -= 1;
+                return Err(SinkDown);
+            }
+            let count = self.received.stream(events).unwrap_or_else(|e| match e {});
+            Ok(count)
+        }
+    }
+
+    fn setup() -> InMemoryStorage<TestAggregate, TestEvent, TestDomainEvent> {
+        let mut storage = InMemoryStorage::with_outbox();
+        storage
+            .save_publishing(&mut TestAggregate::named(1, "first"))
+            .unwrap();
+        storage
+            .save_publishing(&mut TestAggregate::named(2, "second"))
+            .unwrap();
+        storage
+    }
+
+    fn relay(sink: FlakySink) -> Relay<FlakySink> {
+        Relay::new(sink).with_sleep(|_| {})
+    }
+
+    #[test]
+    fn should_retry_failed_delivery() {
+        let mut storage = setup();
+        let mut sut = relay(FlakySink::failing(2));
+
+        let count = sut.relay(&mut storage).unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(sut.sink().attempts, 4);
+        assert_eq!(
+            sut.sink().received,
+            vec![
+                (Id::new(1), Renamed(1, "first")),
+                (Id::new(2), Renamed(2, "second"))
+            ]
+        );
+        assert_eq!(storage.undelivered(), vec![]);
+    }
+
+    #[test]
+    fn should_keep_entries_undelivered_when_sink_keeps_failing() {
+        let mut storage = setup();
+        let mut sut = relay(FlakySink::failing(100));
+
+        assert_eq!(sut.relay(&mut storage), Err(SinkDown));
+
+        assert_eq!(sut.sink().attempts, Backoff::default().attempts);
+        assert_eq!(storage.undelivered().len(), 2);
+    }
+
+    #[test]
+    fn should_not_redeliver_delivered_entries() {
+        let mut storage = setup();
+        let mut sut = relay(FlakySink::failing(0));
+        sut.relay(&mut storage).unwrap();
+
+        storage
+            .save_publishing(&mut TestAggregate::named(3, "third"))
+            .unwrap();
+        let count = sut.relay(&mut storage).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(sut.sink().received.len(), 3);
+    }
+
+    #[test]
+    fn should_grow_delay_up_to_max() {
+        let sut = Backoff {
+            attempts: 10,
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(500),
+        };
+
+        let delays: Vec<_> = (0..4).map(|n| sut.delay(n).as_millis()).collect();
+
+        assert_eq!(delays, vec![100, 200, 400, 500]);
+    }
+}
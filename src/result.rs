@@ -1,77 +1,465 @@
+use std::any;
+use std::convert::Infallible;
 use std::error::Error as StdError;
 use std::fmt;
+use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Error {
     inner: InnerError,
+    source: Option<Arc<dyn StdError + Send + Sync>>,
 }
 
+/// Category of failure, e.g. to choose HTTP status code
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    NotFound,
+    AlreadyExists,
+    Validation,
+    Concurrency,
+    Storage,
+    InconsistentEvent,
+    Other,
+}
+
+/// Id or entity of any type kept as its type name and `Debug` text
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ErasedValue {
+    type_name: &'static str,
+    text: String,
+}
+
+impl ErasedValue {
+    pub fn new<T: fmt::Debug>(value: &T) -> Self {
+        Self {
+            type_name: any::type_name::<T>(),
+            text: format!("{:?}", value),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for ErasedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Problem with the value at `path`, e.g. `items[2].quantity`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Gathers all validation problems instead of stopping at the first one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects failures of already evaluated validators
+    pub fn combine<P, E, I>(results: I) -> std::result::Result<(), Self>
+    where
+        I: IntoIterator<Item = (P, std::result::Result<(), E>)>,
+        P: Into<String>,
+        E: fmt::Display,
+    {
+        let mut errors = Self::new();
+        for (path, result) in results {
+            errors.check(path, result);
+        }
+        errors.into_result()
+    }
+
+    pub fn add(&mut self, path: impl Into<String>, message: impl fmt::Display) {
+        self.errors.push(FieldError {
+            path: path.into(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Records failure under `path` and returns the value otherwise
+    pub fn check<T, E>(
+        &mut self,
+        path: impl Into<String>,
+        result: std::result::Result<T, E>,
+    ) -> Option<T>
+    where
+        E: fmt::Display,
+    {
+        match result {
+            Ok(x) => Some(x),
+            Err(e) => {
+                self.add(path, e);
+                None
+            }
+        }
+    }
+
+    /// Adds errors of a nested value with paths prefixed, e.g. `items[1].quantity`
+    pub fn nest(&mut self, prefix: &str, nested: ValidationErrors) {
+        for e in nested.errors {
+            let path = if e.path.is_empty() {
+                prefix.to_string()
+            } else {
+                format!("{}.{}", prefix, e.path)
+            };
+            self.errors.push(FieldError { path, ..e });
+        }
+    }
+
+    pub fn merge(&mut self, other: ValidationErrors) {
+        self.errors.extend(other.errors);
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn into_result(self) -> std::result::Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, e) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            e.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for ValidationErrors {}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_tuple("Error").field(&self.inner).finish()
+        self.inner.fmt(f)
     }
 }
 
 impl fmt::Display for InnerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use InnerError::*;
         match self {
-            InnerError::ByMessage(m) => f.write_str(m),
+            ByMessage(m) => f.write_str(m),
+            NotFound(id) => write!(f, "Not found: {}", id),
+            AlreadyExists(id) => write!(f, "Already exists: {}", id),
+            Validation(m) => write!(f, "Validation failed: {}", m),
+            InvalidFields(errors) => write!(f, "Validation failed: {}", errors),
+            Concurrency(m) => write!(f, "Concurrent modification: {}", m),
+            Storage(m) => write!(f, "Storage failure: {}", m),
+            InconsistentEvent(m) => write!(f, "Inconsistent event: {}", m),
         }
     }
 }
 
-impl StdError for Error {}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match &self.source {
+            Some(e) => Some(e.as_ref()),
+            None => None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct AlreadyExists<T>(pub T);
 #[derive(Debug)]
 pub struct NotFound<T>(pub T);
+/// Item clashes with another one on the named unique key
+#[derive(Debug)]
+pub struct UniqueViolation<T> {
+    pub key: &'static str,
+    pub item: T,
+}
+/// Rejection of a new item
+#[derive(Debug)]
+pub enum AddError<T> {
+    AlreadyExists(AlreadyExists<T>),
+    UniqueViolation(UniqueViolation<T>),
+}
+/// Rejection of an updated item
+#[derive(Debug)]
+pub enum UpdateError<T> {
+    NotFound(NotFound<T>),
+    UniqueViolation(UniqueViolation<T>),
+}
+/// Referenced entities which do not exist
+#[derive(Debug)]
+pub struct DanglingReferences<T>(pub Vec<T>);
+/// Referrers which prevent deletion
+#[derive(Debug)]
+pub struct RestrictedDeletion<T>(pub Vec<T>);
+
+/// Text form of `Id` could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseIdError {
+    WrongPrefix {
+        expected: &'static str,
+        found: String,
+    },
+    InvalidValue {
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ParseIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseIdError::WrongPrefix { expected, found } => {
+                write!(f, "Expected id with prefix `{}`: {}", expected, found)
+            }
+            ParseIdError::InvalidValue { value, reason } => {
+                write!(f, "Invalid id value `{}`: {}", value, reason)
+            }
+        }
+    }
+}
+
+impl StdError for ParseIdError {}
 
 impl<T: fmt::Debug> StdError for AlreadyExists<T> {}
 impl<T: fmt::Debug> StdError for NotFound<T> {}
+impl<T: fmt::Debug> StdError for UniqueViolation<T> {}
+impl<T: fmt::Debug> StdError for AddError<T> {}
+impl<T: fmt::Debug> StdError for UpdateError<T> {}
+impl<T: fmt::Debug> StdError for DanglingReferences<T> {}
+impl<T: fmt::Debug> StdError for RestrictedDeletion<T> {}
 
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum InnerError {
     ByMessage(String),
+    NotFound(ErasedValue),
+    AlreadyExists(ErasedValue),
+    Validation(String),
+    InvalidFields(ValidationErrors),
+    Concurrency(String),
+    Storage(String),
+    InconsistentEvent(String),
 }
 
+/// Errors are equal by kind and message regardless of their sources
 impl PartialEq for Error {
     fn eq(&self, other: &Self) -> bool {
-        use InnerError::*;
-        match (&self.inner, &other.inner) {
-            (ByMessage(x), ByMessage(y)) if x == y => true,
-            _ => false,
-        }
+        self.inner == other.inner
     }
 }
 
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.inner {
-            InnerError::ByMessage(msg) => f.write_str(&msg),
+        fmt::Display::fmt(&self.inner, f)?;
+        if let Some(source) = &self.source {
+            write!(f, " (caused by: {:?})", source)?;
         }
+        Ok(())
     }
 }
 
 impl Error {
     fn new(inner: InnerError) -> Self {
-        Error { inner }
+        Error {
+            inner,
+            source: None,
+        }
     }
 
     pub fn from_text(text: String) -> Self {
         Self::new(InnerError::ByMessage(text))
     }
+
+    pub fn not_found<T: fmt::Debug>(id: &T) -> Self {
+        Self::new(InnerError::NotFound(ErasedValue::new(id)))
+    }
+
+    pub fn already_exists<T: fmt::Debug>(id: &T) -> Self {
+        Self::new(InnerError::AlreadyExists(ErasedValue::new(id)))
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::new(InnerError::Validation(message.into()))
+    }
+
+    /// Aggregate was changed by someone else since it was loaded
+    pub fn concurrency(message: impl Into<String>) -> Self {
+        Self::new(InnerError::Concurrency(message.into()))
+    }
+
+    pub fn storage(message: impl Into<String>) -> Self {
+        Self::new(InnerError::Storage(message.into()))
+    }
+
+    /// Event can not be applied to the state it was loaded for
+    pub fn inconsistent_event(message: impl Into<String>) -> Self {
+        Self::new(InnerError::InconsistentEvent(message.into()))
+    }
+
+    /// Attaches underlying cause reported by `source()`
+    pub fn with_source<E>(mut self, source: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match &self.inner {
+            InnerError::ByMessage(_) => ErrorKind::Other,
+            InnerError::NotFound(_) => ErrorKind::NotFound,
+            InnerError::AlreadyExists(_) => ErrorKind::AlreadyExists,
+            InnerError::Validation(_) | InnerError::InvalidFields(_) => ErrorKind::Validation,
+            InnerError::Concurrency(_) => ErrorKind::Concurrency,
+            InnerError::Storage(_) => ErrorKind::Storage,
+            InnerError::InconsistentEvent(_) => ErrorKind::InconsistentEvent,
+        }
+    }
+
+    /// Id of the missing or duplicate entity
+    pub fn id(&self) -> Option<&ErasedValue> {
+        match &self.inner {
+            InnerError::NotFound(id) | InnerError::AlreadyExists(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// All failures when error was converted from `ValidationErrors`
+    pub fn validation_errors(&self) -> Option<&ValidationErrors> {
+        match &self.inner {
+            InnerError::InvalidFields(errors) => Some(errors),
+            _ => None,
+        }
+    }
+}
+
+impl From<ValidationErrors> for Error {
+    fn from(value: ValidationErrors) -> Self {
+        Self::new(InnerError::InvalidFields(value))
+    }
 }
 
 impl<T: fmt::Debug> From<AlreadyExists<T>> for Error {
     fn from(value: AlreadyExists<T>) -> Self {
-        Self::from_text(format!("{0:#?}", value))
+        Self::already_exists(&value.0)
     }
 }
 
 impl<T: fmt::Debug> From<NotFound<T>> for Error {
     fn from(value: NotFound<T>) -> Self {
-        Self::from_text(format!("{0:#?}", value))
+        Self::not_found(&value.0)
+    }
+}
+
+impl<T: fmt::Debug> From<UniqueViolation<T>> for Error {
+    fn from(value: UniqueViolation<T>) -> Self {
+        Self::validation(value.to_string())
+    }
+}
+
+impl<T: fmt::Debug> From<AddError<T>> for Error {
+    fn from(value: AddError<T>) -> Self {
+        match value {
+            AddError::AlreadyExists(e) => e.into(),
+            AddError::UniqueViolation(e) => e.into(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> From<UpdateError<T>> for Error {
+    fn from(value: UpdateError<T>) -> Self {
+        match value {
+            UpdateError::NotFound(e) => e.into(),
+            UpdateError::UniqueViolation(e) => e.into(),
+        }
+    }
+}
+
+impl<T> From<AlreadyExists<T>> for AddError<T> {
+    fn from(value: AlreadyExists<T>) -> Self {
+        AddError::AlreadyExists(value)
+    }
+}
+
+impl<T> From<UniqueViolation<T>> for AddError<T> {
+    fn from(value: UniqueViolation<T>) -> Self {
+        AddError::UniqueViolation(value)
+    }
+}
+
+impl<T> From<NotFound<T>> for UpdateError<T> {
+    fn from(value: NotFound<T>) -> Self {
+        UpdateError::NotFound(value)
+    }
+}
+
+impl<T> From<UniqueViolation<T>> for UpdateError<T> {
+    fn from(value: UniqueViolation<T>) -> Self {
+        UpdateError::UniqueViolation(value)
+    }
+}
+
+impl<T: fmt::Debug> From<DanglingReferences<T>> for Error {
+    fn from(value: DanglingReferences<T>) -> Self {
+        Self::not_found(&value.0)
+    }
+}
+
+impl<T: fmt::Debug> From<RestrictedDeletion<T>> for Error {
+    fn from(value: RestrictedDeletion<T>) -> Self {
+        Self::validation(value.to_string())
+    }
+}
+
+impl From<ParseIdError> for Error {
+    fn from(value: ParseIdError) -> Self {
+        Self::validation(value.to_string()).with_source(value)
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(value: Infallible) -> Self {
+        match value {}
     }
 }
 
@@ -95,7 +483,116 @@ impl<T: fmt::Debug> fmt::Display for NotFound<T> {
     }
 }
 
+impl<T: fmt::Debug> fmt::Display for UniqueViolation<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unique key `{}` violated by: {:?}", self.key, self.item)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Display for AddError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddError::AlreadyExists(e) => e.fmt(f),
+            AddError::UniqueViolation(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Display for UpdateError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpdateError::NotFound(e) => e.fmt(f),
+            UpdateError::UniqueViolation(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Display for DanglingReferences<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Dangling references: {:?}", self.0)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Display for RestrictedDeletion<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Deletion restricted by: {:?}", self.0)
+    }
+}
+
 pub type Result<S> = std::result::Result<S, Error>;
 
 pub type CreationResult<T, S = ()> = std::result::Result<S, AlreadyExists<T>>;
 pub type UpdateResult<T, S = ()> = std::result::Result<S, NotFound<T>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_keep_kind_and_id_of_converted_errors() {
+        let sut: Error = NotFound(42).into();
+
+        assert_eq!(sut.kind(), ErrorKind::NotFound);
+        assert_eq!(sut.id().map(ErasedValue::text), Some("42"));
+        assert_eq!(sut.id().map(ErasedValue::type_name), Some("i32"));
+        assert_eq!(sut.to_string(), "Not found: 42");
+    }
+
+    fn positive(x: i32) -> std::result::Result<(), String> {
+        if x > 0 {
+            Ok(())
+        } else {
+            Err(format!("{} is not positive", x))
+        }
+    }
+
+    #[test]
+    fn should_collect_all_validation_failures() {
+        let mut line = ValidationErrors::new();
+        line.check("quantity", positive(0));
+        line.add("", "discontinued");
+        let mut sut = ValidationErrors::combine(vec![("id", positive(1)), ("total", positive(-1))])
+            .unwrap_err();
+
+        sut.nest("items[2]", line);
+        let err: Error = sut.into();
+
+        assert_eq!(err.kind(), ErrorKind::Validation);
+        assert_eq!(err.validation_errors().map(ValidationErrors::len), Some(3));
+        assert_eq!(
+            err.to_string(),
+            "Validation failed: total: -1 is not positive; \
+             items[2].quantity: 0 is not positive; items[2]: discontinued"
+        );
+    }
+
+    #[test]
+    fn should_chain_source() {
+        let cause = ParseIdError::WrongPrefix {
+            expected: "order",
+            found: "x_1".to_string(),
+        };
+
+        let sut = Error::storage("could not read snapshot").with_source(cause.clone());
+
+        assert_eq!(sut.kind(), ErrorKind::Storage);
+        assert_eq!(sut.to_string(), "Storage failure: could not read snapshot");
+        assert_eq!(
+            sut.source().map(ToString::to_string),
+            Some(cause.to_string())
+        );
+    }
+
+    #[test]
+    fn should_stay_cloneable_and_comparable() {
+        let sut =
+            Error::concurrency("version 3 expected").with_source(ParseIdError::InvalidValue {
+                value: "x".to_string(),
+                reason: "nan".to_string(),
+            });
+
+        assert_eq!(sut.clone(), Error::concurrency("version 3 expected"));
+        assert_ne!(sut, Error::validation("version 3 expected"));
+    }
+}
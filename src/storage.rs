@@ -1,12 +1,21 @@
 use crate::changable::Changable;
-use crate::identifiable::{GetId, Id};
-use crate::result::Result;
-use crate::streamable::{KindOfEvent, Streamable, StreamableInContext, Unstreamable};
-use crate::streaming::StreamAdapter;
-use std::error::Error as StdError;
+use crate::details::DetailsOwner;
+use crate::domain_events::DomainEventSource;
+use crate::id_generation::IdGenerator;
+use crate::identifiable::{GetId, Id, Identifiable, Owned, QualifiedId};
+use crate::references::Existence;
+use crate::relay::Outbox;
+use crate::result::{NotFound, Result};
+use crate::streamable::{
+    EventKind, KindOfEvent, Streamable, StreamableInContext, TrackedChanges, Unstreamable,
+};
+use crate::streaming::Stream;
+use crate::transactional_stream::TransactionalStream;
+use std::convert::Infallible;
 use std::fmt;
 use std::hash::Hash;
 use std::result::Result as StdResult;
+use std::slice;
 
 struct EventEnvelope<T: GetId, TEvent> {
     pub id: Id<T::IdentifiableType>,
@@ -47,11 +56,34 @@ where
     }
 }
 
-pub struct InMemoryStorage<T, TEvent>
+struct OutboxEntry<T: GetId, TEvent> {
+    envelope: EventEnvelope<T, TEvent>,
+    delivered: bool,
+}
+
+impl<T, TEvent> fmt::Debug for OutboxEntry<T, TEvent>
+where
+    T: GetId,
+    Id<T::IdentifiableType>: fmt::Debug,
+    TEvent: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OutboxEntry")
+            .field("envelope", &self.envelope)
+            .field("delivered", &self.delivered)
+            .finish()
+    }
+}
+
+/// Keeps events as values of the current `TEvent`, so it has no schema
+/// versions. Events which outlive the process belong in `EventStore`,
+/// which versions payloads and upcasts old ones on load.
+pub struct InMemoryStorage<T, TEvent, TDomainEvent = ()>
 where
     T: GetId,
 {
     events: Vec<EventEnvelope<T, TEvent>>,
+    outbox: Vec<OutboxEntry<T, TDomainEvent>>,
 }
 
 impl<T, TEvent> InMemoryStorage<T, TEvent>
@@ -60,7 +92,22 @@ where
     Id<T::IdentifiableType>: Clone,
 {
     pub fn new() -> Self {
-        Self { events: Vec::new() }
+        Self::with_outbox()
+    }
+}
+
+impl<T, TEvent, TDomainEvent> InMemoryStorage<T, TEvent, TDomainEvent>
+where
+    T: Changable<EventType = TEvent> + GetId,
+    Id<T::IdentifiableType>: Clone,
+{
+    /// Storage which also keeps domain events of saved aggregates
+    /// (see `save_publishing`) until they are relayed.
+    pub fn with_outbox() -> Self {
+        Self {
+            events: Vec::new(),
+            outbox: Vec::new(),
+        }
     }
 
     fn select_events<'a>(
@@ -89,7 +136,7 @@ where
             .cloned()
     }
 
-    pub fn load(&mut self, id: &Id<T::IdentifiableType>) -> Result<T>
+    pub fn load(&self, id: &Id<T::IdentifiableType>) -> Result<T>
     where
         T: Unstreamable<EventType = TEvent>,
         TEvent: Clone,
@@ -98,7 +145,36 @@ where
         T::load(events)
     }
 
-    pub fn load_all(&mut self) -> Result<Vec<T>>
+    /// Loads aggregate into `empty` one, see `Unstreamable::load_into`
+    pub fn load_into(&self, id: &Id<T::IdentifiableType>, empty: T) -> Result<T>
+    where
+        T: Unstreamable<EventType = TEvent>,
+        TEvent: Clone,
+    {
+        let events = self.select_events(id);
+        T::load_into(empty, events)
+    }
+
+    /// Loads the owner aggregate and finds the detail in it
+    pub fn load_detail<D>(&self, id: &QualifiedId<D::IdentifiableType>) -> Result<D>
+    where
+        T: Unstreamable<EventType = TEvent> + DetailsOwner<D>,
+        TEvent: Clone,
+        D: GetId + Clone,
+        D::IdentifiableType: Owned,
+        <D::IdentifiableType as Owned>::OwnerType:
+            Identifiable<IdType = <T::IdentifiableType as Identifiable>::IdType>,
+        QualifiedId<D::IdentifiableType>: Clone + fmt::Debug,
+        <<D::IdentifiableType as Owned>::OwnerType as Identifiable>::IdType: Clone,
+    {
+        let owner = self.load(&id.owner().clone().convert())?;
+        owner
+            .detail_by_qualified_id(id)
+            .cloned()
+            .ok_or_else(|| NotFound(id.clone()).into())
+    }
+
+    pub fn load_all(&self) -> Result<Vec<T>>
     where
         T: Unstreamable<EventType = TEvent>,
         TEvent: Clone + KindOfEvent,
@@ -109,40 +185,233 @@ where
         T::load_many(all_events)
     }
 
-    pub fn save(&mut self, mut root: T) -> StdResult<usize, Box<dyn StdError>>
+    /// Appends pending changes of the aggregate and acknowledges them.
+    /// Nothing is appended if streaming fails.
+    pub fn save(&mut self, root: &mut T) -> Result<usize>
+    where
+        T: Streamable<EventType = TEvent>,
+    {
+        self.append(slice::from_mut(root), |root, events| root.stream_to(events))
+    }
+
+    /// Appends pending changes of all the aggregates or none of them
+    pub fn save_all(&mut self, roots: &mut [T]) -> Result<usize>
     where
         T: Streamable<EventType = TEvent>,
     {
+        self.append(roots, |root, events| root.stream_to(events))
+    }
+
+    /// Appends changes which `stream` produces for each of `roots` in one
+    /// transaction and acknowledges them once it is committed
+    fn append<F>(&mut self, roots: &mut [T], mut stream: F) -> Result<usize>
+    where
+        T: TrackedChanges,
+        F: FnMut(&mut T, &mut Vec<TEvent>) -> StdResult<usize, Infallible>,
+    {
+        let mut log = TransactionalStream::new(&mut self.events);
+        let mut trx = log.begin();
+        let mut tokens = Vec::with_capacity(roots.len());
+        for root in roots.iter_mut() {
+            let id = root.get_id();
+            let mut events = Vec::new();
+            tokens.push(root.pending_changes());
+            stream(root, &mut events).unwrap_or_else(|e| match e {});
+            trx.stream(
+                events
+                    .into_iter()
+                    .map(|e| EventEnvelope::new(id.clone(), e)),
+            )?;
+        }
+        let count = trx.commit()?;
+        for (root, token) in roots.iter_mut().zip(tokens) {
+            root.acknowledge(token);
+        }
+        Ok(count)
+    }
+
+    /// Allocates id for a new aggregate created by `create` and saves it
+    pub fn save_new<G, F>(&mut self, ids: &mut G, create: F) -> Result<T>
+    where
+        T: Streamable<EventType = TEvent>,
+        G: IdGenerator<T::IdentifiableType>,
+        F: FnOnce(Id<T::IdentifiableType>) -> Result<T>,
+    {
+        let mut root = create(ids.next_id()?)?;
+        self.save(&mut root)?;
+        Ok(root)
+    }
+
+    /// Saves changes together with domain events raised by the aggregate.
+    /// Both are written only if streaming of changes succeeds.
+    pub fn save_publishing(&mut self, root: &mut T) -> Result<usize>
+    where
+        T: Streamable<EventType = TEvent> + DomainEventSource<DomainEvent = TDomainEvent>,
+    {
+        let count = self.save(root)?;
         let id = root.get_id();
-        let to_envelope = |e| EventEnvelope::new(id.clone(), e);
-        let mut adapter = StreamAdapter::new(&mut self.events, to_envelope);
-        root.stream_to(&mut adapter)
+        self.outbox
+            .extend(root.take_domain_events().into_iter().map(|e| OutboxEntry {
+                envelope: EventEnvelope::new(id.clone(), e),
+                delivered: false,
+            }));
+        Ok(count)
     }
 
-    pub fn save_in_context<TCtx>(
-        &mut self,
-        ctx: &mut TCtx,
-        root: &mut T,
-    ) -> StdResult<usize, Box<dyn StdError>>
+    pub fn outbox(&self) -> impl Iterator<Item = (&Id<T::IdentifiableType>, &TDomainEvent)> {
+        self.outbox
+            .iter()
+            .map(|x| (&x.envelope.id, &x.envelope.event))
+    }
+
+    pub fn save_in_context<TCtx>(&mut self, ctx: &mut TCtx, root: &mut T) -> Result<usize>
     where
         T: StreamableInContext<TCtx>,
     {
-        let id = root.get_id();
-        let to_envelope = |e| EventEnvelope::new(id.clone(), e);
-        let mut adapter = StreamAdapter::new(&mut self.events, to_envelope);
-        root.stream_in_context_to(ctx, &mut adapter)
+        self.append(slice::from_mut(root), |root, events| {
+            root.stream_in_context_to(ctx, events)
+        })
     }
 }
 
-impl<T, TEvent> fmt::Debug for InMemoryStorage<T, TEvent>
+impl<T, TEvent, TDomainEvent> fmt::Debug for InMemoryStorage<T, TEvent, TDomainEvent>
 where
     T: GetId,
     TEvent: fmt::Debug,
+    TDomainEvent: fmt::Debug,
     Id<T::IdentifiableType>: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("InMemoryStorage")
             .field("events", &self.events)
+            .field("outbox", &self.outbox)
             .finish()
     }
 }
+
+impl<T, TEvent, TDomainEvent> Existence<T::IdentifiableType>
+    for InMemoryStorage<T, TEvent, TDomainEvent>
+where
+    T: GetId,
+    TEvent: KindOfEvent,
+{
+    /// Aggregate exists if its last creation or deletion event is creation
+    fn exists(&self, id: &Id<T::IdentifiableType>) -> bool {
+        self.events
+            .iter()
+            .filter(|x| &x.id == id)
+            .fold(false, |exists, x| match x.event.kind_of_event() {
+                EventKind::Creation => true,
+                EventKind::Deletion => false,
+                _ => exists,
+            })
+    }
+}
+
+/// Entries carry id of the aggregate which raised them, so that relay
+/// can route or deduplicate them per aggregate
+impl<T, TEvent, TDomainEvent> Outbox for InMemoryStorage<T, TEvent, TDomainEvent>
+where
+    T: GetId,
+    Id<T::IdentifiableType>: Clone,
+    TDomainEvent: Clone,
+{
+    type Entry = (Id<T::IdentifiableType>, TDomainEvent);
+
+    fn undelivered(&self) -> Vec<(usize, Self::Entry)> {
+        self.outbox
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| !x.delivered)
+            .map(|(pos, x)| (pos, (x.envelope.id.clone(), x.envelope.event.clone())))
+            .collect()
+    }
+
+    fn mark_delivered(&mut self, position: usize) -> bool {
+        match self.outbox.get_mut(position) {
+            Some(entry) => {
+                entry.delivered = true;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id_generation::Sequence;
+    use crate::test_utils::{TestAggregate, TestDomainEvent, TestEvent};
+    use pretty_assertions::assert_eq;
+    use TestDomainEvent::Renamed;
+
+    type Sut = InMemoryStorage<TestAggregate, TestEvent, TestDomainEvent>;
+
+    #[test]
+    fn should_load_saved() {
+        let mut sut = Sut::with_outbox();
+        sut.save(&mut TestAggregate::named(1, "first")).unwrap();
+        sut.save(&mut TestAggregate::named(2, "second")).unwrap();
+
+        let loaded = sut.load(&Id::new(2)).unwrap();
+
+        assert_eq!(loaded, TestAggregate::named(2, "second"));
+    }
+
+    #[test]
+    fn should_not_save_acknowledged_changes_again() {
+        let mut sut = Sut::with_outbox();
+        let mut root = TestAggregate::named(1, "first");
+        sut.save(&mut root).unwrap();
+        root.rename("second").unwrap();
+
+        let count = sut.save(&mut root).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(sut.load(&Id::new(1)).unwrap(), root);
+        assert_eq!(sut.save(&mut root).unwrap(), 0);
+    }
+
+    #[test]
+    fn should_allocate_id_on_save() {
+        let mut sut = Sut::with_outbox();
+        let mut ids = Sequence::new();
+        let create = |id: Id<TestAggregate>| Ok(TestAggregate::named(*id.raw(), "new"));
+
+        sut.save_new(&mut ids, create).unwrap();
+        let second = sut.save_new(&mut ids, create).unwrap();
+
+        assert_eq!(second.id, 2);
+        assert_eq!(sut.load(&Id::new(2)).unwrap(), second);
+    }
+
+    #[test]
+    fn should_save_domain_events_to_outbox() {
+        let mut sut = Sut::with_outbox();
+
+        sut.save_publishing(&mut TestAggregate::named(1, "first"))
+            .unwrap();
+        sut.save(&mut TestAggregate::named(2, "second")).unwrap();
+
+        let outbox: Vec<_> = sut.outbox().collect();
+        assert_eq!(outbox, vec![(&Id::new(1), &Renamed(1, "first"))]);
+    }
+
+    #[test]
+    fn should_list_undelivered_outbox_entries() {
+        let mut sut = Sut::with_outbox();
+        sut.save_publishing(&mut TestAggregate::named(1, "first"))
+            .unwrap();
+        sut.save_publishing(&mut TestAggregate::named(2, "second"))
+            .unwrap();
+
+        assert!(sut.mark_delivered(0));
+        assert!(!sut.mark_delivered(7));
+
+        assert_eq!(
+            sut.undelivered(),
+            vec![(1, (Id::new(2), Renamed(2, "second")))]
+        );
+    }
+}
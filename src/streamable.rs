@@ -1,20 +1,34 @@
-use super::changable::Changable;
+use super::changable::{Changable, ChangableInContext};
+use crate::changes::PendingChanges;
 use crate::contextual::Contextual;
 use crate::historic::Historic;
 use crate::streaming::*;
+use std::collections::HashMap;
 use std::hash::Hash;
-use std::{collections::HashMap, error::Error};
 
-pub trait Streamable: Historic {
-    fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, Box<dyn Error>>
+/// Tracks changes which are not yet persisted. Every `Undoable` tracks
+/// them in its `Record`.
+pub trait TrackedChanges {
+    fn pending_changes(&mut self) -> PendingChanges;
+
+    fn acknowledge(&mut self, token: PendingChanges);
+}
+
+/// Streams changes which are not yet persisted. Streaming itself does not
+/// clear them: `pending_changes` token taken before streaming is passed to
+/// `acknowledge` after the stream succeeded, so failed save leaves changes
+/// pending and successful one never re-emits them.
+pub trait Streamable: Historic + TrackedChanges {
+    fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, S::Error>
     where
         S: Stream<Self::EventType>;
 
-    // TODO: Result<Vec<..>>
-    fn take_changes(&mut self) -> Vec<Self::EventType> {
+    fn take_changes(&mut self) -> crate::result::Result<Vec<Self::EventType>> {
+        let token = self.pending_changes();
         let mut result = Vec::new();
-        self.stream_to(&mut result).unwrap();
-        result
+        self.stream_to(&mut result)?;
+        self.acknowledge(token);
+        Ok(result)
     }
 }
 
@@ -29,19 +43,24 @@ pub trait KindOfEvent {
     fn kind_of_event(&self) -> EventKind;
 }
 
-pub trait StreamableInContext<TCtx>: Historic {
+pub trait StreamableInContext<TCtx>: Historic + TrackedChanges {
     fn stream_in_context_to<S>(
         &mut self,
         context: &mut TCtx,
         stream: &mut S,
-    ) -> Result<usize, Box<dyn Error>>
+    ) -> Result<usize, S::Error>
     where
         S: Stream<Self::EventType>;
 
-    fn take_changes_in_context(&mut self, context: &mut TCtx) -> Vec<Self::EventType> {
+    fn take_changes_in_context(
+        &mut self,
+        context: &mut TCtx,
+    ) -> crate::result::Result<Vec<Self::EventType>> {
+        let token = self.pending_changes();
         let mut result = Vec::new();
-        let _count = self.stream_in_context_to(context, &mut result).unwrap();
-        result
+        let _count = self.stream_in_context_to(context, &mut result)?;
+        self.acknowledge(token);
+        Ok(result)
     }
 }
 
@@ -49,9 +68,12 @@ impl<T: Historic, TCtx> Historic for Contextual<T, TCtx> {
     type EventType = T::EventType;
 }
 
-impl<T: Changable, TCtx> Changable for Contextual<T, TCtx> {
+impl<T, TCtx> Changable for Contextual<T, TCtx>
+where
+    T: ChangableInContext<TCtx>,
+{
     fn apply(&mut self, event: Self::EventType) -> Self::EventType {
-        self.subject.apply(event)
+        self.subject.apply_in_context(&mut self.context, event)
     }
 }
 
@@ -59,7 +81,7 @@ impl<T, TCtx> Streamable for Contextual<T, TCtx>
 where
     T: StreamableInContext<TCtx>,
 {
-    fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, Box<dyn Error>>
+    fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, S::Error>
     where
         S: Stream<Self::EventType>,
     {
@@ -67,11 +89,30 @@ where
     }
 }
 
+impl<T, TCtx> TrackedChanges for Contextual<T, TCtx>
+where
+    T: TrackedChanges,
+{
+    fn pending_changes(&mut self) -> PendingChanges {
+        self.subject.pending_changes()
+    }
+
+    fn acknowledge(&mut self, token: PendingChanges) {
+        self.subject.acknowledge(token)
+    }
+}
+
 pub trait Unstreamable: Changable + Default + Sized {
     fn load<'a, I>(events: I) -> crate::result::Result<Self>
     where
         I: IntoIterator<Item = Self::EventType>;
 
+    /// Like `load` but replays events into `empty` aggregate, e.g. one with
+    /// validators attached which `Default` can not provide
+    fn load_into<I>(empty: Self, events: I) -> crate::result::Result<Self>
+    where
+        I: IntoIterator<Item = Self::EventType>;
+
     fn load_many<I, ID>(events: I) -> crate::result::Result<Vec<Self>>
     where
         Self::EventType: KindOfEvent,
@@ -87,7 +128,14 @@ where
     where
         I: IntoIterator<Item = Self::EventType>,
     {
-        let mut result = Self::default();
+        Self::load_into(Self::default(), events)
+    }
+
+    fn load_into<I>(empty: Self, events: I) -> crate::result::Result<Self>
+    where
+        I: IntoIterator<Item = Self::EventType>,
+    {
+        let mut result = empty;
         for e in events {
             let _non_undoable_change = result.apply(e);
         }
@@ -135,6 +183,7 @@ mod tests {
     use std::mem;
 
     use super::*;
+    use crate::changable::Plain;
     use crate::contextual::InContext;
     use crate::identifiable::{Id, Identifiable};
     use pretty_assertions::assert_eq;
@@ -156,6 +205,26 @@ mod tests {
         assert_eq!(1, count);
     }
 
+    #[test]
+    fn apply_in_context() {
+        let mut sut = MyStreamable.in_context(MyContext { name: "before" });
+
+        let undo = sut.apply(Captured(MyContext { name: "after" }));
+
+        assert_eq!(undo, Captured(MyContext { name: "before" }));
+        assert_eq!(sut.context, MyContext { name: "after" });
+    }
+
+    #[test]
+    fn apply_plain_changable_in_context() {
+        let mut sut = Plain(MyUnstreamable::default()).in_context(MyContext { name: "unused" });
+
+        let undo = sut.apply(Created(Id::new(42), "red"));
+
+        assert_eq!(undo, Deleted(Id::new(42)));
+        assert_eq!(sut.subject, Plain(MyUnstreamable(42, "red")));
+    }
+
     #[test]
     fn should_load_multiple() {
         let events = vec![
@@ -250,7 +319,7 @@ mod tests {
             &mut self,
             context: &mut MyContext,
             stream: &mut S,
-        ) -> Result<usize, Box<dyn Error>>
+        ) -> Result<usize, S::Error>
         where
             S: Stream<Self::EventType>,
         {
@@ -258,6 +327,21 @@ mod tests {
         }
     }
 
+    impl TrackedChanges for MyStreamable {
+        fn pending_changes(&mut self) -> PendingChanges {
+            PendingChanges::default()
+        }
+
+        fn acknowledge(&mut self, _token: PendingChanges) {}
+    }
+
+    impl ChangableInContext<MyContext> for MyStreamable {
+        fn apply_in_context(&mut self, context: &mut MyContext, event: MyEvent) -> MyEvent {
+            let Captured(captured) = event;
+            Captured(mem::replace(context, captured))
+        }
+    }
+
     impl Historic for MyStreamable {
         type EventType = MyEvent;
     }
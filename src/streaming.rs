@@ -1,6 +1,11 @@
-use std::error::Error;
+use std::convert::Infallible;
+use std::mem;
+
 pub trait Stream<TEvent>: Sized {
-    fn stream<I>(&mut self, events: I) -> Result<usize, Box<dyn Error>>
+    /// Failure reported by the sink, e.g. concurrency conflict or I/O error
+    type Error;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
     where
         I: IntoIterator<Item = TEvent>;
 }
@@ -9,7 +14,9 @@ impl<S, TEvent> Stream<TEvent> for &mut S
 where
     S: Stream<TEvent>,
 {
-    fn stream<I>(&mut self, events: I) -> Result<usize, Box<dyn Error>>
+    type Error = S::Error;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
     where
         I: IntoIterator<Item = TEvent>,
     {
@@ -18,7 +25,9 @@ where
 }
 
 impl<TEvent> Stream<TEvent> for Vec<TEvent> {
-    fn stream<I>(&mut self, events: I) -> Result<usize, Box<dyn Error>>
+    type Error = Infallible;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
     where
         I: IntoIterator<Item = TEvent>,
     {
@@ -42,7 +51,9 @@ where
     TInner: Stream<TInnerEvent>,
     F: Fn(TEvent) -> TInnerEvent,
 {
-    fn stream<I>(&mut self, events: I) -> Result<usize, Box<dyn Error>>
+    type Error = TInner::Error;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
     where
         I: IntoIterator<Item = TEvent>,
     {
@@ -61,11 +72,12 @@ pub struct StreamPipe<'a, P, Dest> {
 
 impl<'a, TEvent, P, Dst> Stream<TEvent> for StreamPipe<'a, P, Dst>
 where
-    TEvent: Clone,
     P: FnMut(TEvent) -> Option<TEvent>,
     Dst: Stream<TEvent>,
 {
-    fn stream<I>(&mut self, events: I) -> Result<usize, Box<dyn Error>>
+    type Error = Dst::Error;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
     where
         I: IntoIterator<Item = TEvent>,
     {
@@ -73,3 +85,348 @@ where
             .stream(events.into_iter().filter_map(&mut self.pipe))
     }
 }
+
+/// Combinators over streams. Unless stated otherwise the returned count is
+/// the one reported by the wrapped sink and an error of the wrapped sink is
+/// returned as is, after it has possibly accepted a part of events.
+pub trait StreamExt<TEvent>: Stream<TEvent> {
+    /// Streams same events to `self` and then to `other`. Returns count of
+    /// `self`. If `self` fails `other` is not called; if `other` fails
+    /// events stay written to `self`.
+    fn tee<O>(self, other: O) -> Tee<Self, O>
+    where
+        O: Stream<TEvent, Error = Self::Error>,
+    {
+        Tee(self, other)
+    }
+
+    /// Passes only events matching the predicate
+    fn filter<P>(self, predicate: P) -> Filter<Self, P>
+    where
+        P: FnMut(&TEvent) -> bool,
+    {
+        Filter(self, predicate)
+    }
+
+    /// Calls `f` for each event just before passing it to the sink
+    fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        F: FnMut(&TEvent),
+    {
+        Inspect(self, f)
+    }
+
+    fn map_err<F, E>(self, f: F) -> MapErr<Self, F>
+    where
+        F: FnMut(Self::Error) -> E,
+    {
+        MapErr(self, f)
+    }
+
+    /// Accepts events of type `E` and passes them to `self` in chunks of
+    /// `size`, the last one may be shorter. Returns count of events in the
+    /// chunks accepted by `self`.
+    fn batch<E>(self, size: usize) -> Batch<Self>
+    where
+        Self: Stream<Vec<E>>,
+    {
+        assert!(size > 0, "Batch size should be positive");
+        Batch { inner: self, size }
+    }
+
+    /// Keeps events in memory until `flush`. Events which are never
+    /// flushed are dropped together with the buffer.
+    fn buffered(self) -> Buffered<Self, TEvent> {
+        Buffered {
+            inner: self,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<S: Stream<TEvent>, TEvent> StreamExt<TEvent> for S {}
+
+pub struct Tee<S1, S2>(S1, S2);
+
+impl<S1, S2> Tee<S1, S2> {
+    pub fn into_inner(self) -> (S1, S2) {
+        (self.0, self.1)
+    }
+}
+
+impl<TEvent, S1, S2> Stream<TEvent> for Tee<S1, S2>
+where
+    TEvent: Clone,
+    S1: Stream<TEvent>,
+    S2: Stream<TEvent, Error = S1::Error>,
+{
+    type Error = S1::Error;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        let events: Vec<_> = events.into_iter().collect();
+        let count = self.0.stream(events.iter().cloned())?;
+        self.1.stream(events)?;
+        Ok(count)
+    }
+}
+
+pub struct Filter<S, P>(S, P);
+
+impl<TEvent, S, P> Stream<TEvent> for Filter<S, P>
+where
+    S: Stream<TEvent>,
+    P: FnMut(&TEvent) -> bool,
+{
+    type Error = S::Error;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        self.0.stream(events.into_iter().filter(&mut self.1))
+    }
+}
+
+pub struct Inspect<S, F>(S, F);
+
+impl<TEvent, S, F> Stream<TEvent> for Inspect<S, F>
+where
+    S: Stream<TEvent>,
+    F: FnMut(&TEvent),
+{
+    type Error = S::Error;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        self.0.stream(events.into_iter().inspect(&mut self.1))
+    }
+}
+
+pub struct MapErr<S, F>(S, F);
+
+impl<TEvent, S, F, E> Stream<TEvent> for MapErr<S, F>
+where
+    S: Stream<TEvent>,
+    F: FnMut(S::Error) -> E,
+{
+    type Error = E;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        self.0.stream(events).map_err(&mut self.1)
+    }
+}
+
+pub struct Batch<S> {
+    inner: S,
+    size: usize,
+}
+
+impl<TEvent, S> Stream<TEvent> for Batch<S>
+where
+    S: Stream<Vec<TEvent>>,
+{
+    type Error = S::Error;
+
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        let mut chunks = Vec::new();
+        let mut chunk = Vec::with_capacity(self.size);
+        for e in events {
+            chunk.push(e);
+            if chunk.len() == self.size {
+                chunks.push(mem::replace(&mut chunk, Vec::with_capacity(self.size)));
+            }
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+
+        let lens: Vec<_> = chunks.iter().map(Vec::len).collect();
+        let accepted = self.inner.stream(chunks)?;
+        Ok(lens.iter().take(accepted).sum())
+    }
+}
+
+pub struct Buffered<S, TEvent> {
+    inner: S,
+    buffer: Vec<TEvent>,
+}
+
+impl<S, TEvent> Buffered<S, TEvent>
+where
+    S: Stream<TEvent>,
+{
+    pub fn pending(&self) -> &[TEvent] {
+        &self.buffer
+    }
+
+    /// Streams buffered events to the sink. Buffer is cleared only if the
+    /// sink succeeds. Sink which fails partway may have accepted a part of
+    /// the buffer already, so retrying a failed flush resends that part;
+    /// retry only into all-or-nothing or duplicate-tolerant sinks.
+    pub fn flush(&mut self) -> Result<usize, S::Error>
+    where
+        TEvent: Clone,
+    {
+        let count = self.inner.stream(self.buffer.iter().cloned())?;
+        self.buffer.clear();
+        Ok(count)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, TEvent> Stream<TEvent> for Buffered<S, TEvent>
+where
+    S: Stream<TEvent>,
+{
+    type Error = S::Error;
+
+    /// Never fails; returns count of buffered events
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        let len_before = self.buffer.len();
+        self.buffer.extend(events);
+        Ok(self.buffer.len() - len_before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Accepts events until full
+    struct Limited {
+        received: Vec<i32>,
+        capacity: usize,
+    }
+
+    impl Limited {
+        fn new(capacity: usize) -> Self {
+            Self {
+                received: Vec::new(),
+                capacity,
+            }
+        }
+    }
+
+    impl Stream<i32> for Limited {
+        type Error = &'static str;
+
+        fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+        where
+            I: IntoIterator<Item = i32>,
+        {
+            let mut count = 0;
+            for e in events {
+                if self.received.len() == self.capacity {
+                    return Err("full");
+                }
+                self.received.push(e);
+                count += 1;
+            }
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn should_tee_to_both_sinks() {
+        let mut storage = Limited::new(10);
+        let mut audit = Limited::new(10);
+
+        let count = (&mut storage).tee(&mut audit).stream(vec![1, 2]);
+
+        assert_eq!(count, Ok(2));
+        assert_eq!(storage.received, vec![1, 2]);
+        assert_eq!(audit.received, vec![1, 2]);
+    }
+
+    #[test]
+    fn should_keep_events_in_first_sink_when_second_fails() {
+        let mut storage = Limited::new(10);
+        let mut audit = Limited::new(1);
+
+        let result = (&mut storage).tee(&mut audit).stream(vec![1, 2]);
+
+        assert_eq!(result, Err("full"));
+        assert_eq!(storage.received, vec![1, 2]);
+        assert_eq!(audit.received, vec![1]);
+    }
+
+    #[test]
+    fn should_count_filtered_events() {
+        let mut sink = Vec::new();
+
+        let count = (&mut sink).filter(|e| e % 2 == 0).stream(1..=5);
+
+        assert_eq!(count, Ok(2));
+        assert_eq!(sink, vec![2, 4]);
+    }
+
+    #[test]
+    fn should_inspect_accepted_events() {
+        let mut seen = Vec::new();
+
+        let result = Limited::new(1)
+            .inspect(|e| seen.push(*e))
+            .stream(vec![1, 2, 3]);
+
+        assert_eq!(result, Err("full"));
+        assert_eq!(seen, vec![1, 2]);
+    }
+
+    #[test]
+    fn should_map_error() {
+        let result = Limited::new(0).map_err(|e| e.len()).stream(vec![1]);
+
+        assert_eq!(result, Err(4));
+    }
+
+    #[test]
+    fn should_batch_events_and_count_them() {
+        let mut sink: Vec<Vec<i32>> = Vec::new();
+
+        let count = (&mut sink).batch(2).stream(1..=5);
+
+        assert_eq!(count, Ok(5));
+        assert_eq!(sink, vec![vec![1, 2], vec![3, 4], vec![5]]);
+    }
+
+    #[test]
+    fn should_keep_whole_buffer_when_flush_fails() {
+        let mut sut = Limited::new(1).buffered();
+        sut.stream(vec![1, 2]).unwrap();
+
+        assert_eq!(sut.flush(), Err("full"));
+
+        assert_eq!(sut.pending(), &[1, 2]);
+        assert_eq!(sut.into_inner().received, vec![1]);
+    }
+
+    #[test]
+    fn should_write_buffered_events_on_flush() {
+        let mut sut = Vec::new().buffered();
+        sut.stream(vec![1]).unwrap();
+        sut.stream(vec![2]).unwrap();
+
+        assert_eq!(sut.flush(), Ok(2));
+
+        assert_eq!(sut.pending(), &[] as &[i32]);
+        assert_eq!(sut.into_inner(), vec![1, 2]);
+    }
+}
@@ -1,16 +1,21 @@
 use crate::changable::Changable;
+use crate::changes::{PendingChanges, Removals};
 use crate::historic::Historic;
-use crate::streamable::Streamable;
+use crate::streamable::{Streamable, TrackedChanges};
 use crate::streaming::Stream;
 use crate::undoable::{UndoManager, Undoable};
-use std::error::Error;
+use std::error::Error as StdError;
+use std::fmt;
+use std::mem;
 
+/// Streams pending changes as they are produced by redoing them: the
+/// aggregate is rewound only for the duration of `stream_to` and is
+/// restored afterwards even if the sink fails or panics.
 pub struct UndoRedoStreamingStrategy<'a, U: Undoable>
 where
     U::EventType: Clone,
 {
     um: UndoManager<'a, U>,
-    count: usize,
 }
 
 impl<'a, U: Undoable> UndoRedoStreamingStrategy<'a, U>
@@ -18,23 +23,56 @@ where
     U::EventType: Clone,
 {
     pub fn new(undoable: &'a mut U) -> Self {
-        let count = undoable.changes_mut().history_len();
-        let mut um = undoable.undo_manager();
-        um.undo_all();
-        Self { um, count }
+        Self {
+            um: undoable.undo_manager(),
+        }
     }
+}
 
-    pub fn events(&mut self) -> impl IntoIterator<Item = &U::EventType> {
+/// Undoes last `count` changes and redoes them on drop, leaving no trace
+/// of their removal for tokens taken before
+struct Rewound<'b, 'a, U: Undoable>
+where
+    U::EventType: Clone,
+{
+    um: &'b mut UndoManager<'a, U>,
+    count: usize,
+    removals: Removals,
+}
+
+impl<'b, 'a, U: Undoable> Rewound<'b, 'a, U>
+where
+    U::EventType: Clone,
+{
+    fn new(um: &'b mut UndoManager<'a, U>, count: usize) -> Self {
+        let removals = um.subject_mut().changes_mut().removals();
+        let mut undone = 0;
+        while undone < count && um.undo() {
+            undone += 1;
+        }
+        Self {
+            um,
+            count: undone,
+            removals,
+        }
+    }
+
+    fn events(&mut self) -> impl Iterator<Item = &U::EventType> {
         self.um.iter_future_history(self.count).rev()
     }
 }
 
-impl<'a, U: Undoable> Drop for UndoRedoStreamingStrategy<'a, U>
+impl<'b, 'a, U: Undoable> Drop for Rewound<'b, 'a, U>
 where
     U::EventType: Clone,
 {
     fn drop(&mut self) {
         self.um.redo_n(self.count);
+        let removals = mem::take(&mut self.removals);
+        self.um
+            .subject_mut()
+            .changes_mut()
+            .restore_removals(removals);
     }
 }
 
@@ -49,8 +87,8 @@ impl<'a, U: Undoable> Changable for UndoRedoStreamingStrategy<'a, U>
 where
     U::EventType: Clone,
 {
-    fn apply(&mut self, _event: Self::EventType) -> Self::EventType {
-        unimplemented!("Cannot modify through the strategy")
+    fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+        self.um.subject_mut().apply(event)
     }
 }
 
@@ -58,11 +96,26 @@ impl<'a, U: Undoable> Streamable for UndoRedoStreamingStrategy<'a, U>
 where
     U::EventType: Clone,
 {
-    fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, Box<dyn Error>>
+    fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, S::Error>
     where
         S: Stream<U::EventType>,
     {
-        stream.stream(self.events().into_iter().cloned())
+        let count = self.um.pending_changes().len();
+        let mut rewound = Rewound::new(&mut self.um, count);
+        stream.stream(rewound.events().cloned())
+    }
+}
+
+impl<'a, U: Undoable> TrackedChanges for UndoRedoStreamingStrategy<'a, U>
+where
+    U::EventType: Clone,
+{
+    fn pending_changes(&mut self) -> PendingChanges {
+        self.um.pending_changes()
+    }
+
+    fn acknowledge(&mut self, token: PendingChanges) {
+        self.um.acknowledge(token)
     }
 }
 
@@ -84,7 +137,7 @@ where
     }
 
     pub fn events(&mut self) -> impl IntoIterator<Item = &U::EventType> {
-        self.um.iter_history()
+        self.um.iter_pending()
     }
 }
 
@@ -92,7 +145,7 @@ impl<'a, U: Undoable> Streamable for CloneRedoStreamingStrategy<'a, U>
 where
     U::EventType: Clone,
 {
-    fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, Box<dyn Error>>
+    fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, S::Error>
     where
         S: Stream<U::EventType>,
     {
@@ -100,6 +153,19 @@ where
     }
 }
 
+impl<'a, U: Undoable> TrackedChanges for CloneRedoStreamingStrategy<'a, U>
+where
+    U::EventType: Clone,
+{
+    fn pending_changes(&mut self) -> PendingChanges {
+        self.um.pending_changes()
+    }
+
+    fn acknowledge(&mut self, token: PendingChanges) {
+        self.um.acknowledge(token)
+    }
+}
+
 impl<'a, U: Undoable> Historic for CloneRedoStreamingStrategy<'a, U>
 where
     U::EventType: Clone,
@@ -111,7 +177,391 @@ impl<'a, U: Undoable> Changable for CloneRedoStreamingStrategy<'a, U>
 where
     U::EventType: Clone,
 {
-    fn apply(&mut self, _event: Self::EventType) -> Self::EventType {
-        unimplemented!("Cannot modify through the strategy")
+    fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+        self.um.subject_mut().apply(event)
+    }
+}
+
+/// Streams pending changes by moving redo events out of the record, so
+/// events need not be `Clone` and large payloads are not copied. Undo
+/// events are kept.
+///
+/// When the sink fails, redo events it has not pulled yet are put back,
+/// so the changes are streamed again by the next attempt. Events the sink
+/// pulled before failing can not be put back without `Clone`, as well as
+/// events streamed successfully but never acknowledged: while such changes
+/// are pending, streaming fails with `MoveRedoError::RedoMoved` rather than
+/// skipping them.
+pub struct MoveRedoStreamingStrategy<'a, U: Undoable> {
+    um: UndoManager<'a, U>,
+}
+
+/// Failure of `MoveRedoStreamingStrategy::stream_to`
+#[derive(Debug, PartialEq, Eq)]
+pub enum MoveRedoError<E> {
+    /// Sink failed, redo events it has not pulled are put back
+    Sink(E),
+    /// Redo of a pending change was moved out by an earlier attempt
+    RedoMoved,
+}
+
+impl<E: fmt::Display> fmt::Display for MoveRedoError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveRedoError::Sink(e) => e.fmt(f),
+            MoveRedoError::RedoMoved => {
+                f.write_str("Redo of a pending change was moved out by an earlier attempt")
+            }
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> StdError for MoveRedoError<E> {}
+
+impl<'a, U: Undoable> MoveRedoStreamingStrategy<'a, U> {
+    pub fn new(undoable: &'a mut U) -> Self {
+        Self {
+            um: undoable.undo_manager(),
+        }
+    }
+
+    /// Streams pending changes unless an earlier attempt has moved out
+    /// redo of any of them
+    pub fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, MoveRedoError<S::Error>>
+    where
+        S: Stream<U::EventType>,
+    {
+        let changes = self.um.subject_mut().changes_mut();
+        if changes.pending().iter().any(|c| c.redo().is_none()) {
+            return Err(MoveRedoError::RedoMoved);
+        }
+        let mut redos = self.um.take_pending_redos().into_iter();
+        let result = stream.stream((&mut redos).map(|(_, e)| e));
+        if result.is_err() {
+            self.um.restore_pending_redos(redos);
+        }
+        result.map_err(MoveRedoError::Sink)
+    }
+}
+
+impl<'a, U: Undoable> TrackedChanges for MoveRedoStreamingStrategy<'a, U> {
+    fn pending_changes(&mut self) -> PendingChanges {
+        self.um.pending_changes()
+    }
+
+    fn acknowledge(&mut self, token: PendingChanges) {
+        self.um.acknowledge(token)
+    }
+}
+
+impl<'a, U: Undoable> Historic for MoveRedoStreamingStrategy<'a, U> {
+    type EventType = U::EventType;
+}
+
+impl<'a, U: Undoable> Changable for MoveRedoStreamingStrategy<'a, U> {
+    fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+        self.um.subject_mut().apply(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changes::{FullChange, FullChanges, Record};
+    use crate::invariants::Invariants;
+    use crate::result::Error;
+    use crate::test_utils::{TestAggregate, TestEvent};
+    use pretty_assertions::assert_eq;
+    use std::panic;
+    use TestEvent::*;
+
+    struct FailingSink;
+
+    impl Stream<TestEvent> for FailingSink {
+        type Error = &'static str;
+
+        fn stream<I>(&mut self, _events: I) -> Result<usize, Self::Error>
+        where
+            I: IntoIterator<Item = TestEvent>,
+        {
+            Err("sink is down")
+        }
+    }
+
+    struct PanickingSink;
+
+    impl Stream<TestEvent> for PanickingSink {
+        type Error = &'static str;
+
+        fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+        where
+            I: IntoIterator<Item = TestEvent>,
+        {
+            events.into_iter().next();
+            panic!("sink crashed")
+        }
+    }
+
+    /// Event which cannot be cloned
+    #[derive(Debug, Eq, PartialEq)]
+    struct Resized(Vec<u8>);
+
+    #[derive(Default)]
+    struct Blob {
+        content: Vec<u8>,
+        changes: Record<FullChange<Resized>>,
+    }
+
+    impl Blob {
+        fn resize(&mut self, len: usize) {
+            let mut trx = self.begin_changes();
+            trx.mutate(|subj| {
+                let redo = Resized(vec![0; len]);
+                let undo = subj.apply(Resized(vec![0; len]));
+                Ok::<_, Error>(FullChanges::only(FullChange::new(redo, undo)))
+            })
+            .unwrap();
+            trx.commit().unwrap();
+        }
+    }
+
+    impl Historic for Blob {
+        type EventType = Resized;
+    }
+
+    impl Changable for Blob {
+        fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+            Resized(std::mem::replace(&mut self.content, event.0))
+        }
+    }
+
+    impl Invariants for Blob {}
+
+    impl Undoable for Blob {
+        fn changes_mut(&mut self) -> &mut Record<FullChange<Self::EventType>> {
+            &mut self.changes
+        }
+    }
+
+    fn given_renamed() -> TestAggregate {
+        let mut root = TestAggregate::named(1, "first");
+        root.rename("second").unwrap();
+        root
+    }
+
+    fn save(root: &mut TestAggregate, stream: &mut impl Stream<TestEvent>) -> bool {
+        let token = root.pending_changes();
+        let saved = root.stream_to(stream).is_ok();
+        if saved {
+            root.acknowledge(token);
+        }
+        saved
+    }
+
+    #[test]
+    fn should_keep_changes_pending_when_stream_fails() {
+        let mut root = TestAggregate::named(1, "first");
+
+        assert!(!save(&mut root, &mut FailingSink));
+
+        assert_eq!(root.take_changes(), Ok(vec![Created(1), Renamed("first")]));
+    }
+
+    #[test]
+    fn should_stream_each_change_once() {
+        let mut root = TestAggregate::named(1, "first");
+        let mut stream = Vec::new();
+        save(&mut root, &mut stream);
+        root.rename("second").unwrap();
+
+        save(&mut root, &mut stream);
+        save(&mut root, &mut stream);
+
+        assert_eq!(
+            stream,
+            vec![Created(1), Renamed("first"), Renamed("second")]
+        );
+    }
+
+    #[test]
+    fn should_stream_same_changes_with_both_strategies() {
+        let mut root = given_renamed();
+        let mut by_undo_redo = Vec::new();
+        let mut by_clone_redo = Vec::new();
+
+        UndoRedoStreamingStrategy::new(&mut root)
+            .stream_to(&mut by_undo_redo)
+            .unwrap();
+        CloneRedoStreamingStrategy::new(&mut root)
+            .stream_to(&mut by_clone_redo)
+            .unwrap();
+
+        assert_eq!(by_undo_redo, by_clone_redo);
+        assert_eq!(root, given_renamed());
+        assert_eq!(root.changes_mut().history_len(), 3);
+    }
+
+    #[test]
+    fn should_not_stream_again_after_save_by_undo_redo() {
+        let mut root = given_renamed();
+        let mut stream = Vec::new();
+
+        for _ in 0..2 {
+            let mut sut = UndoRedoStreamingStrategy::new(&mut root);
+            let token = sut.pending_changes();
+            sut.stream_to(&mut stream).unwrap();
+            sut.acknowledge(token);
+        }
+
+        assert_eq!(
+            stream,
+            vec![Created(1), Renamed("first"), Renamed("second")]
+        );
+        assert_eq!(root.pending_changes().len(), 0);
+    }
+
+    #[test]
+    fn should_restore_aggregate_when_sink_fails() {
+        let mut root = given_renamed();
+
+        let result = UndoRedoStreamingStrategy::new(&mut root).stream_to(&mut FailingSink);
+
+        assert_eq!(result, Err("sink is down"));
+        assert_eq!(root, given_renamed());
+        assert_eq!(root.take_changes().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn should_restore_aggregate_when_sink_panics() {
+        let mut root = given_renamed();
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            UndoRedoStreamingStrategy::new(&mut root).stream_to(&mut PanickingSink)
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(root, given_renamed());
+        assert_eq!(root.take_changes().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn should_apply_through_undo_redo_strategy() {
+        let mut root = given_renamed();
+
+        let undo = UndoRedoStreamingStrategy::new(&mut root).apply(Renamed("third"));
+
+        assert_eq!(undo, Renamed("second"));
+        assert_eq!(root.name, "third");
+    }
+
+    #[test]
+    fn should_apply_through_clone_redo_strategy() {
+        let mut root = given_renamed();
+
+        let undo = CloneRedoStreamingStrategy::new(&mut root).apply(Renamed("third"));
+
+        assert_eq!(undo, Renamed("second"));
+        assert_eq!(root.name, "third");
+    }
+
+    #[test]
+    fn should_move_non_clone_events_out() {
+        let mut root = Blob::default();
+        root.resize(2);
+        root.resize(3);
+        let mut stream = Vec::new();
+
+        let count = MoveRedoStreamingStrategy::new(&mut root).stream_to(&mut stream);
+
+        assert_eq!(count, Ok(2));
+        assert_eq!(stream, vec![Resized(vec![0; 2]), Resized(vec![0; 3])]);
+        assert_eq!(root.changes_mut()[0].redo(), None);
+        assert_eq!(root.changes_mut()[1].undo(), &Resized(vec![0; 2]));
+    }
+
+    #[test]
+    fn should_keep_undo_after_moving_redo_out() {
+        let mut root = given_renamed();
+        MoveRedoStreamingStrategy::new(&mut root)
+            .stream_to(&mut Vec::new())
+            .unwrap();
+
+        assert!(root.undo_manager().undo());
+
+        assert_eq!(root.name, "first");
+    }
+
+    #[test]
+    fn should_stream_same_changes_as_clone_redo_strategy() {
+        let mut root = given_renamed();
+        let mut by_clone_redo = Vec::new();
+        let mut by_move_redo = Vec::new();
+
+        CloneRedoStreamingStrategy::new(&mut root)
+            .stream_to(&mut by_clone_redo)
+            .unwrap();
+        MoveRedoStreamingStrategy::new(&mut root)
+            .stream_to(&mut by_move_redo)
+            .unwrap();
+
+        assert_eq!(by_move_redo, by_clone_redo);
+        assert_eq!(root.pending_changes().len(), 3);
+    }
+
+    #[test]
+    fn should_keep_changes_pending_until_acknowledged() {
+        let mut root = Blob::default();
+        root.resize(2);
+        let mut sut = MoveRedoStreamingStrategy::new(&mut root);
+        let token = sut.pending_changes();
+
+        sut.stream_to(&mut Vec::new()).unwrap();
+        assert_eq!(sut.pending_changes().len(), 1);
+
+        sut.acknowledge(token);
+        assert!(sut.pending_changes().is_empty());
+    }
+
+    #[test]
+    fn should_put_redos_back_when_sink_fails_before_pulling() {
+        let mut root = given_renamed();
+
+        let result = MoveRedoStreamingStrategy::new(&mut root).stream_to(&mut FailingSink);
+
+        assert_eq!(result, Err(MoveRedoError::Sink("sink is down")));
+        assert_eq!(
+            root.take_changes(),
+            Ok(vec![Created(1), Renamed("first"), Renamed("second")])
+        );
+    }
+
+    /// Sink which pulls the first event and fails
+    struct FailingAfterFirstSink;
+
+    impl Stream<TestEvent> for FailingAfterFirstSink {
+        type Error = &'static str;
+
+        fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+        where
+            I: IntoIterator<Item = TestEvent>,
+        {
+            events.into_iter().next();
+            Err("sink is down")
+        }
+    }
+
+    #[test]
+    fn should_refuse_to_stream_changes_pulled_by_failed_sink() {
+        let mut root = given_renamed();
+        let mut sut = MoveRedoStreamingStrategy::new(&mut root);
+        let token = sut.pending_changes();
+        assert!(sut.stream_to(&mut FailingAfterFirstSink).is_err());
+
+        let mut stream = Vec::new();
+        let result = sut.stream_to(&mut stream);
+
+        assert_eq!(result, Err(MoveRedoError::RedoMoved));
+        assert_eq!(stream, vec![]);
+        assert_eq!(sut.pending_changes(), token);
     }
 }
@@ -0,0 +1,221 @@
+use crate::changable::Changable;
+use crate::identifiable::{GetId, Id};
+use crate::result::Result;
+use crate::storage::InMemoryStorage;
+use crate::streamable::{KindOfEvent, Unstreamable};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+/// Tenant whose data is isolated from data of other tenants
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TenantId(String);
+
+impl TenantId {
+    pub fn new(raw: impl Into<String>) -> Self {
+        Self(raw.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for TenantId {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Context which knows the current tenant, e.g. `Contextual::context`
+pub trait TenantContext {
+    fn tenant_id(&self) -> &TenantId;
+}
+
+impl TenantContext for TenantId {
+    fn tenant_id(&self) -> &TenantId {
+        self
+    }
+}
+
+/// Storage partitioned by tenant. Each tenant has its own
+/// `InMemoryStorage`, so the same aggregate id in different tenants
+/// refers to unrelated aggregates.
+pub struct MultiTenantStorage<T, TEvent, TDomainEvent = ()>
+where
+    T: GetId,
+{
+    tenants: HashMap<TenantId, InMemoryStorage<T, TEvent, TDomainEvent>>,
+}
+
+impl<T, TEvent, TDomainEvent> MultiTenantStorage<T, TEvent, TDomainEvent>
+where
+    T: Changable<EventType = TEvent> + GetId,
+    Id<T::IdentifiableType>: Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            tenants: HashMap::new(),
+        }
+    }
+
+    /// Data of `tenant` for reading, `None` if nothing was written for it
+    pub fn get(&self, tenant: &TenantId) -> Option<&InMemoryStorage<T, TEvent, TDomainEvent>> {
+        self.tenants.get(tenant)
+    }
+
+    /// Handle which writes only data of `tenant`. Creates the tenant's
+    /// partition, so use `get` for reads.
+    pub fn tenant(&mut self, tenant: &TenantId) -> &mut InMemoryStorage<T, TEvent, TDomainEvent> {
+        self.tenants
+            .entry(tenant.clone())
+            .or_insert_with(InMemoryStorage::with_outbox)
+    }
+
+    /// Handle for the tenant of the current context
+    pub fn for_context<TCtx>(
+        &mut self,
+        context: &TCtx,
+    ) -> &mut InMemoryStorage<T, TEvent, TDomainEvent>
+    where
+        TCtx: TenantContext,
+    {
+        self.tenant(context.tenant_id())
+    }
+
+    /// All aggregates of the tenant
+    pub fn load_all(&self, tenant: &TenantId) -> Result<Vec<T>>
+    where
+        T: Unstreamable<EventType = TEvent>,
+        TEvent: Clone + KindOfEvent,
+        Id<T::IdentifiableType>: Hash,
+    {
+        match self.get(tenant) {
+            Some(storage) => storage.load_all(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Deletes all data of the tenant and tells whether there was any
+    pub fn purge_tenant(&mut self, tenant: &TenantId) -> bool {
+        self.tenants.remove(tenant).is_some()
+    }
+
+    pub fn tenants(&self) -> impl Iterator<Item = &TenantId> {
+        self.tenants.keys()
+    }
+}
+
+impl<T, TEvent, TDomainEvent> Default for MultiTenantStorage<T, TEvent, TDomainEvent>
+where
+    T: Changable<EventType = TEvent> + GetId,
+    Id<T::IdentifiableType>: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contextual::InContext;
+    use crate::references::Existence;
+    use crate::test_utils::{TestAggregate, TestEvent};
+    use pretty_assertions::assert_eq;
+
+    type Sut = MultiTenantStorage<TestAggregate, TestEvent>;
+
+    struct Request {
+        tenant: TenantId,
+    }
+
+    impl TenantContext for Request {
+        fn tenant_id(&self) -> &TenantId {
+            &self.tenant
+        }
+    }
+
+    fn acme() -> TenantId {
+        "acme".into()
+    }
+
+    fn globex() -> TenantId {
+        "globex".into()
+    }
+
+    fn setup() -> Sut {
+        let mut sut = Sut::new();
+        sut.tenant(&acme())
+            .save(&mut TestAggregate::named(1, "acme first"))
+            .unwrap();
+        sut.tenant(&globex())
+            .save(&mut TestAggregate::named(1, "globex first"))
+            .unwrap();
+        sut
+    }
+
+    #[test]
+    fn should_isolate_same_ids_of_different_tenants() {
+        let sut = setup();
+
+        let acme_root = sut.get(&acme()).unwrap().load(&Id::new(1)).unwrap();
+        let globex_root = sut.get(&globex()).unwrap().load(&Id::new(1)).unwrap();
+
+        assert_eq!(acme_root, TestAggregate::named(1, "acme first"));
+        assert_eq!(globex_root, TestAggregate::named(1, "globex first"));
+    }
+
+    #[test]
+    fn should_load_all_of_tenant_only() {
+        let mut sut = setup();
+        sut.tenant(&acme())
+            .save(&mut TestAggregate::named(2, "acme second"))
+            .unwrap();
+
+        let mut loaded = sut.load_all(&acme()).unwrap();
+        loaded.sort_by_key(|x| x.id);
+
+        assert_eq!(
+            loaded,
+            vec![
+                TestAggregate::named(1, "acme first"),
+                TestAggregate::named(2, "acme second"),
+            ]
+        );
+        assert_eq!(sut.load_all(&"initech".into()).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn should_purge_tenant() {
+        let mut sut = setup();
+
+        assert!(sut.purge_tenant(&acme()));
+
+        assert_eq!(sut.load_all(&acme()).unwrap(), vec![]);
+        assert!(sut.get(&acme()).is_none());
+        assert!(sut.get(&globex()).unwrap().exists(&Id::new(1)));
+        assert_eq!(sut.tenants().collect::<Vec<_>>(), vec![&globex()]);
+        assert!(!sut.purge_tenant(&"initech".into()));
+    }
+
+    #[test]
+    fn should_pick_tenant_from_context() {
+        let mut sut = setup();
+        let mut root =
+            TestAggregate::named(2, "from request").in_context(Request { tenant: globex() });
+
+        sut.for_context(&root.context)
+            .save(&mut root.subject)
+            .unwrap();
+
+        assert!(sut.get(&globex()).unwrap().exists(&Id::new(2)));
+        assert!(!sut.get(&acme()).unwrap().exists(&Id::new(2)));
+    }
+}
@@ -1,6 +1,19 @@
 #![cfg(test)]
 
-use crate::streamable::Streamable;
+use crate::changable::Changable;
+use crate::changes::{FullChange, FullChanges, Record};
+use crate::domain_events::{DomainEventSource, RaisedEvents};
+use crate::historic::Historic;
+use crate::identifiable::{Id, Identifiable};
+use crate::invariants::Invariants;
+use crate::result::{Error, Result};
+use crate::streamable::{EventKind, KindOfEvent, Streamable};
+use crate::streaming::Stream;
+use crate::streaming_strategies::CloneRedoStreamingStrategy;
+use crate::undoable::Undoable;
+use std::mem;
+use std::result::Result as StdResult;
+use TestEvent::*;
 
 pub(crate) trait AssumeChangesSaved {
     fn assume_changes_saved(&mut self);
@@ -8,6 +21,123 @@ pub(crate) trait AssumeChangesSaved {
 
 impl<T: Streamable> AssumeChangesSaved for T {
     fn assume_changes_saved(&mut self) {
-        drop(self.take_changes())
+        drop(self.take_changes().unwrap())
     }
 }
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum TestEvent {
+    Created(i32),
+    Deleted(i32),
+    Renamed(&'static str),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum TestDomainEvent {
+    Renamed(i32, &'static str),
+}
+
+/// Minimal aggregate for storage related tests
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TestAggregate {
+    pub id: i32,
+    pub name: &'static str,
+    changes: Record<FullChange<TestEvent>>,
+    domain_events: Vec<TestDomainEvent>,
+}
+
+impl PartialEq for TestAggregate {
+    fn eq(&self, other: &Self) -> bool {
+        (self.id, self.name) == (other.id, other.name)
+    }
+}
+
+impl TestAggregate {
+    pub fn new(id: i32) -> Self {
+        let mut result = Self::default();
+        let changes: FullChanges<_> = result.applied(Created(id));
+        result.changes.extend(changes);
+        result
+    }
+
+    pub fn named(id: i32, name: &'static str) -> Self {
+        let mut result = Self::new(id);
+        result.rename(name).unwrap();
+        result
+    }
+
+    pub fn rename(&mut self, name: &'static str) -> Result<()> {
+        let id = self.id;
+        let mut trx = self.begin_changes();
+        trx.mutate(|subj| Ok::<_, Error>(subj.applied(Renamed(name))))?;
+        trx.raise(TestDomainEvent::Renamed(id, name));
+        trx.commit()
+    }
+
+    pub fn delete(&mut self) -> Result<()> {
+        let id = self.id;
+        let mut trx = self.begin_changes();
+        trx.mutate(|subj| Ok::<_, Error>(subj.applied(Deleted(id))))?;
+        trx.commit()
+    }
+}
+
+impl Identifiable for TestAggregate {
+    type IdType = i32;
+
+    fn id(&self) -> Id<Self> {
+        Id::new(self.id)
+    }
+}
+
+impl Historic for TestAggregate {
+    type EventType = TestEvent;
+}
+
+impl KindOfEvent for TestEvent {
+    fn kind_of_event(&self) -> EventKind {
+        match self {
+            Created(_) => EventKind::Creation,
+            Deleted(_) => EventKind::Deletion,
+            Renamed(_) => EventKind::Other,
+        }
+    }
+}
+
+impl Changable for TestAggregate {
+    fn apply(&mut self, event: Self::EventType) -> Self::EventType {
+        match event {
+            Created(id) => {
+                self.id = id;
+                Deleted(id)
+            }
+            Deleted(id) => Created(id),
+            Renamed(name) => Renamed(mem::replace(&mut self.name, name)),
+        }
+    }
+}
+
+impl Invariants for TestAggregate {}
+
+impl Undoable for TestAggregate {
+    fn changes_mut(&mut self) -> &mut Record<FullChange<Self::EventType>> {
+        &mut self.changes
+    }
+
+    fn raised_events(&mut self) -> Option<&mut dyn RaisedEvents> {
+        Some(&mut self.domain_events)
+    }
+}
+
+impl Streamable for TestAggregate {
+    fn stream_to<S>(&mut self, stream: &mut S) -> StdResult<usize, S::Error>
+    where
+        S: Stream<Self::EventType>,
+    {
+        CloneRedoStreamingStrategy::new(self).stream_to(stream)
+    }
+}
+
+impl DomainEventSource for TestAggregate {
+    type DomainEvent = TestDomainEvent;
+}
@@ -0,0 +1,170 @@
+use crate::streaming::Stream;
+
+/// Sink wrapper which publishes events all at once. Events streamed to
+/// a transaction are staged and reach the wrapped sink only on `commit`.
+/// Dropping transaction without commit rolls it back.
+///
+/// This protects against failures of the producer only. `commit` hands
+/// staged events to the wrapped sink in a single call, so the whole
+/// transaction is all-or-nothing only if that sink is, e.g. `Vec` or
+/// a sink writing in a database transaction.
+pub struct TransactionalStream<S> {
+    inner: S,
+}
+
+impl<S> TransactionalStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    pub fn begin<TEvent>(&mut self) -> StreamTransaction<'_, S, TEvent>
+    where
+        S: Stream<TEvent>,
+    {
+        StreamTransaction {
+            sink: &mut self.inner,
+            staged: Vec::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+pub struct StreamTransaction<'a, S, TEvent> {
+    sink: &'a mut S,
+    staged: Vec<TEvent>,
+}
+
+impl<'a, S, TEvent> StreamTransaction<'a, S, TEvent>
+where
+    S: Stream<TEvent>,
+{
+    pub fn staged(&self) -> &[TEvent] {
+        &self.staged
+    }
+
+    /// Streams all staged events to the wrapped sink in one call
+    /// and returns its count. Sink which fails partway keeps what it
+    /// has written so far.
+    pub fn commit(self) -> Result<usize, S::Error> {
+        self.sink.stream(self.staged)
+    }
+
+    pub fn rollback(self) {}
+}
+
+impl<'a, S, TEvent> Stream<TEvent> for StreamTransaction<'a, S, TEvent>
+where
+    S: Stream<TEvent>,
+{
+    type Error = S::Error;
+
+    /// Only stages events so never fails; returns count of staged events
+    fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+    where
+        I: IntoIterator<Item = TEvent>,
+    {
+        let len_before = self.staged.len();
+        self.staged.extend(events);
+        Ok(self.staged.len() - len_before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn write_then_fail<S>(stream: &mut S) -> Result<usize, &'static str>
+    where
+        S: Stream<i32, Error = &'static str>,
+    {
+        stream.stream(vec![1, 2])?;
+        Err("producer failed")
+    }
+
+    struct Sink(Vec<i32>);
+
+    impl Stream<i32> for Sink {
+        type Error = &'static str;
+
+        fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+        where
+            I: IntoIterator<Item = i32>,
+        {
+            let count = self.0.stream(events).unwrap_or_else(|e| match e {});
+            Ok(count)
+        }
+    }
+
+    /// Sink which is not atomic: writes a single event and fails on the next
+    struct Partial(Vec<i32>);
+
+    impl Stream<i32> for Partial {
+        type Error = &'static str;
+
+        fn stream<I>(&mut self, events: I) -> Result<usize, Self::Error>
+        where
+            I: IntoIterator<Item = i32>,
+        {
+            for e in events {
+                if !self.0.is_empty() {
+                    return Err("sink failed");
+                }
+                self.0.push(e);
+            }
+            Ok(self.0.len())
+        }
+    }
+
+    #[test]
+    fn should_publish_staged_events_on_commit() {
+        let mut sut = TransactionalStream::new(Vec::new());
+        let mut trx = sut.begin();
+        trx.stream(vec![1]).unwrap();
+        trx.stream(vec![2, 3]).unwrap();
+
+        assert_eq!(trx.commit(), Ok(3));
+
+        assert_eq!(sut.into_inner(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn should_discard_staged_events_on_rollback() {
+        let mut sut = TransactionalStream::new(Vec::new());
+        let mut trx = sut.begin();
+        trx.stream(vec![1]).unwrap();
+
+        trx.rollback();
+
+        assert_eq!(sut.inner(), &Vec::<i32>::new());
+    }
+
+    #[test]
+    fn should_not_write_anything_when_producer_fails() {
+        let mut sut = TransactionalStream::new(Sink(Vec::new()));
+
+        let mut trx = sut.begin();
+        assert_eq!(write_then_fail(&mut trx), Err("producer failed"));
+        drop(trx);
+
+        assert_eq!(sut.inner().0, vec![]);
+    }
+
+    #[test]
+    fn should_leave_partial_write_of_non_atomic_sink_on_commit() {
+        let mut sut = TransactionalStream::new(Partial(Vec::new()));
+        let mut trx = sut.begin();
+        trx.stream(vec![1, 2]).unwrap();
+
+        assert_eq!(trx.commit(), Err("sink failed"));
+
+        assert_eq!(sut.inner().0, vec![1]);
+    }
+}
@@ -1,18 +1,34 @@
 use crate::changable::Changable;
-use crate::changes::{FullChange, FullChanges, Record};
+use crate::changes::{FullChange, FullChanges, PendingChanges, Record};
+use crate::domain_events::{DomainEventSource, RaisedEvents};
+use crate::invariants::Invariants;
+use crate::result::{Result as DomainResult, ValidationErrors};
+use crate::streamable::TrackedChanges;
+use std::fmt;
 use std::mem;
 
-pub trait Undoable: Changable + Sized {
+pub trait Undoable: Changable + Invariants + Sized {
     fn changes_mut(&mut self) -> &mut Record<FullChange<Self::EventType>>;
 
     fn begin_changes(&mut self) -> Atomic<'_, Self> {
         let check_point = self.changes_mut().history_len();
+        let raised_check_point = self.raised_events().map(|x| x.count());
         Atomic {
             subj: self,
             check_point,
+            raised_check_point,
+            raised: Vec::new(),
+            errors: ValidationErrors::new(),
         }
     }
 
+    /// Domain events of a `DomainEventSource` aggregate, which must be its
+    /// `Vec<DomainEvent>`, so that rollback also discards events committed by
+    /// nested transactions
+    fn raised_events(&mut self) -> Option<&mut dyn RaisedEvents> {
+        None
+    }
+
     fn undo_manager<'a>(&'a mut self) -> UndoManager<'a, Self> {
         UndoManager { subj: self }
     }
@@ -22,9 +38,24 @@ pub trait Undoable: Changable + Sized {
     }
 }
 
+impl<T: Undoable> TrackedChanges for T {
+    fn pending_changes(&mut self) -> PendingChanges {
+        self.changes_mut().pending_changes()
+    }
+
+    fn acknowledge(&mut self, token: PendingChanges) {
+        self.changes_mut().acknowledge(token)
+    }
+}
+
+type Deferred<'a, T> = Box<dyn 'a + FnOnce(&mut T)>;
+
 pub struct Atomic<'a, T: Undoable> {
     subj: &'a mut T,
     check_point: usize,
+    raised_check_point: Option<usize>,
+    raised: Vec<Deferred<'a, T>>,
+    errors: ValidationErrors,
 }
 
 impl<'a, T: Undoable> Atomic<'a, T> {
@@ -60,8 +91,43 @@ impl<'a, T: Undoable> Atomic<'a, T> {
         Ok(())
     }
 
-    pub fn commit(self) {
-        mem::forget(self)
+    /// Like `invoke` but failure is recorded under `path` instead of being
+    /// returned, so that `commit` reports all failures at once
+    pub fn collect<F, R, E>(&mut self, path: &str, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> Result<R, E>,
+        E: fmt::Display,
+    {
+        let result = f(self.subj);
+        self.errors.check(path, result)
+    }
+
+    /// Records validation failure which makes `commit` roll back
+    pub fn reject(&mut self, path: &str, message: impl fmt::Display) {
+        self.errors.add(path, message);
+    }
+
+    /// Domain event is recorded by aggregate only if transaction commits
+    pub fn raise(&mut self, event: T::DomainEvent)
+    where
+        T: DomainEventSource,
+        T::DomainEvent: 'a,
+    {
+        self.raised
+            .push(Box::new(move |subj| subj.domain_events_mut().push(event)));
+    }
+
+    /// Checks aggregate invariants and keeps changes if they hold and no
+    /// failures were collected. Otherwise changes are compensated and
+    /// the collected failures or the violation are returned.
+    pub fn commit(mut self) -> DomainResult<()> {
+        mem::take(&mut self.errors).into_result()?;
+        self.subj.check()?;
+        for record in mem::take(&mut self.raised) {
+            record(self.subj);
+        }
+        mem::forget(self);
+        Ok(())
     }
 }
 
@@ -74,6 +140,10 @@ impl<'a, T: Undoable> UndoManager<'a, T> {
         self.subj.changes_mut()
     }
 
+    pub fn subject_mut(&mut self) -> &mut T {
+        self.subj
+    }
+
     pub fn undo(&mut self) -> bool
     where
         T::EventType: Clone,
@@ -134,7 +204,45 @@ impl<'a, T: Undoable> UndoManager<'a, T> {
     where
         T::EventType: Clone,
     {
-        self.changes_mut().undos().iter().map(|c| c.redo())
+        self.changes_mut()
+            .undos()
+            .iter()
+            .filter_map(FullChange::redo)
+    }
+
+    /// Pending changes whose redo was moved out are skipped
+    pub fn iter_pending(&mut self) -> impl '_ + DoubleEndedIterator<Item = &T::EventType> {
+        self.changes_mut()
+            .pending()
+            .iter()
+            .filter_map(FullChange::redo)
+    }
+
+    /// Moves redo events out of pending changes together with their
+    /// positions among pending changes
+    pub fn take_pending_redos(&mut self) -> Vec<(usize, T::EventType)> {
+        self.changes_mut()
+            .pending_mut()
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(pos, c)| c.take_redo_out().map(|e| (pos, e)))
+            .collect()
+    }
+
+    /// Puts redo events taken by `take_pending_redos` back
+    pub fn restore_pending_redos(&mut self, redos: impl IntoIterator<Item = (usize, T::EventType)>) {
+        let pending = self.changes_mut().pending_mut();
+        for (pos, e) in redos {
+            pending[pos].restore_redo(e);
+        }
+    }
+
+    pub fn pending_changes(&mut self) -> PendingChanges {
+        self.changes_mut().pending_changes()
+    }
+
+    pub fn acknowledge(&mut self, token: PendingChanges) {
+        self.changes_mut().acknowledge(token)
     }
 }
 
@@ -145,6 +253,9 @@ impl<'a, T: Undoable> Drop for Atomic<'a, T> {
         for c in to_compensate {
             self.subj.apply(c.take_undo());
         }
+        if let (Some(count), Some(raised)) = (self.raised_check_point, self.subj.raised_events()) {
+            raised.discard_after(count);
+        }
     }
 }
 
@@ -157,12 +268,12 @@ mod tests {
     use crate::streaming::Stream;
     use crate::streaming_strategies::CloneRedoStreamingStrategy;
     use pretty_assertions::assert_eq;
-    use std::error::Error as StdError;
     use TestEvent::*;
 
     #[derive(Debug, Eq, PartialEq)]
     struct TestEntry {
         state: TestEvent,
+        locked: bool,
         changes: Record<FullChange<TestEvent>>,
     }
 
@@ -184,8 +295,15 @@ mod tests {
             trx.invoke(Self::start)?;
             trx.invoke(Self::start)?; // fail and rollback both starts
 
-            trx.commit();
-            Ok(())
+            trx.commit()
+        }
+
+        fn start_atomically(&mut self) -> crate::result::Result<()> {
+            let mut trx = self.begin_changes();
+
+            trx.invoke(Self::start)?;
+
+            trx.commit()
         }
 
         fn start(&mut self) -> Result<(), String> {
@@ -204,6 +322,18 @@ mod tests {
         }
     }
 
+    impl TestEntry {
+        fn start_twice_collecting(&mut self) -> crate::result::Result<()> {
+            let mut trx = self.begin_changes();
+
+            trx.collect("first", Self::start);
+            trx.collect("second", Self::start);
+            trx.reject("reason", "test");
+
+            trx.commit()
+        }
+    }
+
     impl Historic for TestEntry {
         type EventType = TestEvent;
     }
@@ -221,6 +351,16 @@ mod tests {
         }
     }
 
+    impl Invariants for TestEntry {
+        fn check(&self) -> crate::result::Result<()> {
+            if self.locked && self.state != Stopped {
+                Err("Locked entry should stay stopped".to_string().into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
     impl Undoable for TestEntry {
         fn changes_mut(&mut self) -> &mut Record<FullChange<Self::EventType>> {
             &mut self.changes
@@ -228,7 +368,7 @@ mod tests {
     }
 
     impl Streamable for TestEntry {
-        fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, Box<dyn StdError>>
+        fn stream_to<S>(&mut self, stream: &mut S) -> Result<usize, S::Error>
         where
             S: Stream<Self::EventType>,
         {
@@ -239,6 +379,7 @@ mod tests {
     fn given_stopped() -> TestEntry {
         let sut = TestEntry {
             state: Stopped,
+            locked: false,
             changes: Record::new(),
         };
 
@@ -293,6 +434,34 @@ mod tests {
         assert_eq!(Stopped, sut.state);
 
         let changes = sut.take_changes();
-        assert_eq!(Vec::<TestEvent>::new(), changes);
+        assert_eq!(Ok(Vec::<TestEvent>::new()), changes);
+    }
+
+    #[test]
+    fn should_report_all_collected_failures_and_rollback() {
+        let mut sut = given_stopped();
+
+        let err = sut.start_twice_collecting().unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Validation failed: second: Already started; reason: test"
+        );
+        assert_eq!(Stopped, sut.state);
+        assert_eq!(Ok(Vec::<TestEvent>::new()), sut.take_changes());
+    }
+
+    #[test]
+    fn should_rollback_changes_when_invariants_fail_on_commit() {
+        let mut sut = given_stopped();
+        sut.locked = true;
+
+        assert_eq!(
+            sut.start_atomically(),
+            Err("Locked entry should stay stopped".to_string().into())
+        );
+
+        assert_eq!(Stopped, sut.state);
+        assert_eq!(Ok(Vec::<TestEvent>::new()), sut.take_changes());
     }
 }
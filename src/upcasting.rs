@@ -0,0 +1,130 @@
+use crate::result::{Error, Result};
+
+/// One step of schema migration which turns payload of `stream_type`
+/// written with `source_version` into payload of the next version
+pub trait Upcaster {
+    fn stream_type(&self) -> &str;
+
+    fn source_version(&self) -> u32;
+
+    fn upcast(&self, payload: &str) -> Result<String>;
+}
+
+/// Upcaster made of a plain function
+#[derive(Clone, Copy)]
+pub struct UpcastFn {
+    stream_type: &'static str,
+    source_version: u32,
+    upcast: fn(&str) -> Result<String>,
+}
+
+impl UpcastFn {
+    pub fn new(
+        stream_type: &'static str,
+        source_version: u32,
+        upcast: fn(&str) -> Result<String>,
+    ) -> Self {
+        Self {
+            stream_type,
+            source_version,
+            upcast,
+        }
+    }
+}
+
+impl Upcaster for UpcastFn {
+    fn stream_type(&self) -> &str {
+        self.stream_type
+    }
+
+    fn source_version(&self) -> u32 {
+        self.source_version
+    }
+
+    fn upcast(&self, payload: &str) -> Result<String> {
+        (self.upcast)(payload)
+    }
+}
+
+/// Steps which bring old payloads to the current schema version,
+/// e.g. v1 -> v2 -> v3
+#[derive(Default)]
+pub struct UpcasterChain {
+    steps: Vec<Box<dyn Upcaster>>,
+}
+
+impl UpcasterChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<U>(mut self, step: U) -> Self
+    where
+        U: 'static + Upcaster,
+    {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// Runs the steps needed to turn payload of `version` into `target` one.
+    /// Fails if a step is missing or payload is newer than `target`.
+    pub fn upcast(
+        &self,
+        stream_type: &str,
+        version: u32,
+        payload: &str,
+        target: u32,
+    ) -> Result<String> {
+        if version > target {
+            return Err(Error::inconsistent_event(format!(
+                "{} payload v{} is newer than supported v{}",
+                stream_type, version, target
+            )));
+        }
+        let mut result = payload.to_string();
+        for v in version..target {
+            let step = self
+                .steps
+                .iter()
+                .find(|x| x.stream_type() == stream_type && x.source_version() == v)
+                .ok_or_else(|| {
+                    Error::inconsistent_event(format!("No upcaster for {} v{}", stream_type, v))
+                })?;
+            result = step.upcast(&result)?;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result::ErrorKind;
+    use pretty_assertions::assert_eq;
+
+    fn sut() -> UpcasterChain {
+        UpcasterChain::new()
+            .with(UpcastFn::new("doc", 2, |x| Ok(format!("{}!", x))))
+            .with(UpcastFn::new("doc", 1, |x| Ok(x.to_uppercase())))
+            .with(UpcastFn::new("other", 1, |_| Ok(String::new())))
+    }
+
+    #[test]
+    fn should_run_steps_in_version_order() {
+        assert_eq!(sut().upcast("doc", 1, "hi", 3), Ok("HI!".to_string()));
+        assert_eq!(sut().upcast("doc", 2, "hi", 3), Ok("hi!".to_string()));
+        assert_eq!(sut().upcast("doc", 3, "hi", 3), Ok("hi".to_string()));
+    }
+
+    #[test]
+    fn should_fail_without_step_or_for_newer_payload() {
+        let missing = sut().upcast("doc", 1, "hi", 4).unwrap_err();
+        let newer = sut().upcast("doc", 4, "hi", 3).unwrap_err();
+
+        assert_eq!(
+            missing.to_string(),
+            "Inconsistent event: No upcaster for doc v3"
+        );
+        assert_eq!(newer.kind(), ErrorKind::InconsistentEvent);
+    }
+}